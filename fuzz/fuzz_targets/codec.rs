@@ -0,0 +1,25 @@
+//! Protocol parsers must not panic on any input: `cargo +nightly fuzz run codec`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Types are included by path to fuzz them without the whole server
+#[path = "../../src/api/figura/types/errors.rs"]
+mod errors;
+#[path = "../../src/api/figura/types/c2s.rs"]
+mod c2s;
+#[path = "../../src/api/figura/types/s2c.rs"]
+mod s2c;
+
+use c2s::C2SMessage;
+use errors::MessageLoadError;
+use s2c::S2CMessage;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = C2SMessage::try_from(data) {
+        assert_eq!(C2SMessage::try_from(&*msg.to_array()).unwrap(), msg);
+    }
+    if let Ok(msg) = S2CMessage::try_from(data) {
+        assert_eq!(S2CMessage::try_from(&*msg.to_array()).unwrap(), msg);
+    }
+});
@@ -0,0 +1,12 @@
+//! Avatar parser must not panic or overflow the stack on any upload: `cargo +nightly fuzz run nbt`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/api/figura/types/nbt.rs"]
+#[allow(dead_code)] // Only the parser is fuzzed
+mod nbt;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = nbt::Nbt::from_bytes(data);
+});
@@ -10,10 +10,16 @@ pub enum ApiError {
     BadRequest, // 400
     #[error("unauthorized")]
     Unauthorized, // 401
+    #[error("forbidden")]
+    Forbidden, // 403
     #[error("not found")]
     NotFound, // 404
     #[error("not acceptable")]
     NotAcceptable, // 406
+    #[error("conflict")]
+    Conflict, // 409
+    #[error("invalid avatar: {}", .0.join(", "))]
+    InvalidAvatar(Vec<&'static str>), // 422, names of the failed rules
     #[error("internal server error")]
     Internal, // 500
 }
@@ -23,8 +29,11 @@ impl IntoResponse for ApiError {
         match self {
             ApiError::BadRequest => (StatusCode::BAD_REQUEST, "bad request").into_response(),
             ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized").into_response(),
+            ApiError::Forbidden => (StatusCode::FORBIDDEN, "forbidden").into_response(),
             ApiError::NotAcceptable=> (StatusCode::NOT_ACCEPTABLE, "not acceptable").into_response(),
             ApiError::NotFound => (StatusCode::NOT_FOUND, "not found").into_response(),
+            ApiError::Conflict => (StatusCode::CONFLICT, "conflict").into_response(),
+            ApiError::InvalidAvatar(failed) => (StatusCode::UNPROCESSABLE_ENTITY, format!("invalid avatar: {}", failed.join(", "))).into_response(),
             ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response(),
         }
     }
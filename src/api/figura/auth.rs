@@ -1,9 +1,9 @@
 use axum::{debug_handler, extract::{Query, State}, response::{IntoResponse, Response}, routing::get, Router};
 use reqwest::StatusCode;
 use ring::digest::{self, digest};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::{auth::{has_joined, Userinfo}, utils::rand, AppState};
+use crate::{auth::{has_joined, Userinfo}, state::labels, utils::rand, AppState};
 use super::types::auth::*;
 
 pub fn router() -> Router<AppState> {
@@ -33,44 +33,69 @@ async fn verify(
 ) -> Response {
     let server_id = query.id.clone();
     let username = state.user_manager.pending_remove(&server_id).unwrap().1; // TODO: Add error check
-    let userinfo = match has_joined(
-        state.config.read().await.auth_providers.clone(),
+    let config = state.config.read().await.clone();
+    let (profile, auth_provider) = match has_joined(
+        config.auth_providers,
+        config.auth_strategy,
+        state.auth_health.clone(),
+        config.auth_circuit_breaker,
         &server_id,
         &username
     ).await {
         Ok(d) => d,
-        Err(_e) => {
+        Err(attempts) if attempts.is_internal() => {
             // error!("[Authentication] {e}"); // In auth error log already defined
-            return (StatusCode::INTERNAL_SERVER_ERROR, "internal verify error".to_string()).into_response();
+            state.metrics.inc("sculptor_auth_verify_total{result=\"error\"}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("internal verify error ({attempts})")).into_response();
+        },
+        Err(attempts) => {
+            info!("[Authentication] failed to verify {username}");
+            state.metrics.inc("sculptor_auth_verify_total{result=\"failed\"}");
+            return (StatusCode::BAD_REQUEST, format!("failed to verify ({attempts})")).into_response();
         },
     };
-    if let Some((uuid, auth_provider)) = userinfo {
-        let umanager = state.user_manager;
-        if umanager.is_banned(&uuid) {
-            info!("[Authentication] {username} tried to log in, but was banned");
-            return (StatusCode::BAD_REQUEST, "You're banned!".to_string()).into_response();
-        }
-        info!("[Authentication] {username} logged in using {}", auth_provider.name);
-        let userinfo = Userinfo {
-            username,
-            uuid,
-            token: Some(server_id.clone()),
-            auth_provider,
-            ..Default::default()
+    if !profile.name.eq_ignore_ascii_case(&username) {
+        warn!("[Authentication] {username} tried to log in, but {} confirmed {} instead", auth_provider.name, profile.name);
+        return (StatusCode::BAD_REQUEST, "Username doesn't match the profile!".to_string()).into_response();
+    }
+    let uuid = profile.id;
+    let username = profile.name.clone(); // Canonical name from the provider
+    let umanager = &state.user_manager;
+    if umanager.is_banned(&uuid) {
+        info!("[Authentication] {username} tried to log in, but was banned");
+        let message = match umanager.ban_info(&uuid).and_then(|ban| ban.details()) {
+            Some(details) => format!("You're banned! {details}"),
+            None => "You're banned!".to_string(),
         };
-        match umanager.insert(uuid, server_id.clone(), userinfo.clone()) {
-            Ok(_) => {},
-            Err(_) => {
-                umanager.remove(&uuid);
-                if umanager.insert(uuid, server_id.clone(), userinfo).is_err() {
-                    error!("Old token error after attempting to remove it! Unexpected behavior!");
-                    return (StatusCode::BAD_REQUEST, "second session detected".to_string()).into_response();
-                };
-            }
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+    let bound = state.bindings.bind(uuid, &auth_provider.name).await.unwrap_or_else(|err| {
+        error!("[Authentication] Can't save the provider binding of {uuid}: {err}");
+        true // Still bound in memory
+    });
+    if !bound {
+        warn!("[Authentication] {username} tried to log in using {}, but {uuid} is bound to another provider", auth_provider.name);
+        return (StatusCode::BAD_REQUEST, "This UUID is bound to another authentication provider!".to_string()).into_response();
+    }
+    info!("[Authentication] {username} logged in using {}", auth_provider.name);
+    state.metrics.inc(&format!("sculptor_auth_verify_total{}", labels(&[("result", "success"), ("provider", &auth_provider.name)])));
+    let userinfo = Userinfo {
+        username,
+        uuid,
+        token: Some(server_id.clone()),
+        auth_provider,
+        skin: profile.skin_url(),
+        ..Default::default()
+    };
+    match umanager.insert(uuid, server_id.clone(), userinfo.clone()) {
+        Ok(_) => {},
+        Err(_) => {
+            umanager.remove(&uuid);
+            if umanager.insert(uuid, server_id.clone(), userinfo).is_err() {
+                error!("Old token error after attempting to remove it! Unexpected behavior!");
+                return (StatusCode::BAD_REQUEST, "second session detected".to_string()).into_response();
+            };
         }
-        (StatusCode::OK, server_id.to_string()).into_response()
-    } else {
-        info!("[Authentication] failed to verify {username}");
-        (StatusCode::BAD_REQUEST, "failed to verify".to_string()).into_response()
     }
-}
\ No newline at end of file
+    (StatusCode::OK, server_id.to_string()).into_response()
+}
@@ -4,6 +4,7 @@ use serde_json::Value;
 use tracing::error;
 
 use crate::{
+    state::RateLimits,
     utils::{get_figura_versions, get_motd, FiguraVersions},
     AppState, FIGURA_DEFAULT_VERSION,
 };
@@ -64,6 +65,18 @@ impl Default for RateLimit {
     }
 }
 
+impl From<&RateLimits> for RateLimit {
+    fn from(value: &RateLimits) -> Self {
+        RateLimit {
+            ping_size: value.ping_size,
+            ping_rate: value.ping_rate,
+            equip: value.equip,
+            download: value.download,
+            upload: value.upload,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ServerLimits {
     pub rate: RateLimit,
@@ -72,10 +85,11 @@ pub struct ServerLimits {
 
 // todo: use the above code to implement this
 pub async fn limits(State(state): State<AppState>) -> Json<Value> {
-    let state = &state.config.read().await.limitations;
+    let config = state.config.read().await;
+    let state = &config.limitations;
 
     let limits = ServerLimits {
-        rate: RateLimit::default(),
+        rate: RateLimit::from(&config.rate_limits),
         limits: Limits {
             max_avatar_size: state.max_avatar_size,
             max_avatars: state.max_avatars,
@@ -3,5 +3,8 @@ mod websocket;
 pub mod auth;
 pub mod profile;
 pub mod info;
+mod validation;
 
-pub use websocket::handler as ws;
\ No newline at end of file
+pub use types::S2CMessage;
+pub use validation::validate_avatar;
+pub use websocket::{handler as ws, shutdown as ws_shutdown, enforce_ban, notify_mute, AbuseTracker, ConnectionTracker, Hub};
\ No newline at end of file
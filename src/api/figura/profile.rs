@@ -17,7 +17,7 @@ use super::types::{badges::SpecialBadges, S2CMessage};
 use crate::{
     api::errors::internal_and_log,
     auth::Token,
-    utils::{calculate_file_sha256, format_uuid},
+    utils::{calculate_file_sha256, calculate_sha256, format_uuid},
     ApiError, ApiResult, AppState,
 };
 
@@ -41,10 +41,17 @@ pub struct User {
     pub banned: bool,
 }
 impl User {
-    pub async fn user_info(uuid: Uuid, state: &AppState) -> Result<Self, ApiError> {
+    /// `requester` is the user asking for the profile, if authenticated
+    pub async fn user_info(uuid: Uuid, requester: Option<Uuid>, state: &AppState) -> Result<Self, ApiError> {
         let formatted_uuid = format_uuid(&uuid);
 
-        let avatar_file = format!("avatars/{}.moon", formatted_uuid);
+        // Owner sees the avatar waiting for approval
+        let pending_file = pending_avatar_file(&uuid);
+        let avatar_file = if requester == Some(uuid) && fs::metadata(&pending_file).await.is_ok() {
+            pending_file
+        } else {
+            format!("avatars/{}.moon", formatted_uuid)
+        };
 
         let userinfo = if let Some(info) = state.user_manager.get_by_uuid(&uuid) {
             info
@@ -58,8 +65,10 @@ impl User {
             equipped: vec![],
             equipped_badges: EquippedBadges::default(),
             version: userinfo.version.clone(),
-            banned: userinfo.banned,
+            banned: userinfo.is_banned(),
         };
+        // Shadow banned user still sees own avatar
+        let hidden = user_info.banned || (userinfo.shadow_banned && requester != Some(uuid));
 
         if let Some(settings) = state.config.read().await.advanced_users.get(&uuid) {
             user_info.equipped_badges.special = SpecialBadges::from(
@@ -79,8 +88,10 @@ impl User {
         }
         // Ok(user_info)
 
-        if fs::metadata(&avatar_file).await.is_ok() {
+        // Banned user's avatar is hidden
+        if !hidden && fs::metadata(&avatar_file).await.is_ok() {
             match calculate_file_sha256(&avatar_file) {
+                Ok(hash) if state.blocklist.is_blocked(&hash) => debug!("Avatar of {uuid} is blocked"),
                 Ok(hash) => user_info.equipped.push(json!({
                     "id": "avatar",
                     "owner": &formatted_uuid,
@@ -108,7 +119,19 @@ impl User {
                 user_info.uuid,
                 user_info.username
             );
-            let avatar_file = format!("avatars/{}.moon", user_info.uuid);
+            if state.blocklist.is_blocked(&calculate_sha256(&request_data)) {
+                tracing::warn!("{} ({}) tried to upload a blocked avatar", user_info.uuid, user_info.username);
+                return Err(ApiError::Forbidden);
+            }
+            super::validate_avatar(state, user_info.uuid, &request_data).await?;
+            let pre_approval = state.config.read().await.moderation.pre_approval;
+            let avatar_file = if pre_approval {
+                fs::create_dir_all("avatars/pending").await.map_err(internal_and_log)?;
+                tracing::info!("Avatar of {} is waiting for approval", user_info.uuid);
+                pending_avatar_file(&user_info.uuid)
+            } else {
+                format!("avatars/{}.moon", user_info.uuid)
+            };
             let mut file = BufWriter::new(
                 fs::File::create(&avatar_file)
                     .await
@@ -117,28 +140,45 @@ impl User {
             io::copy(&mut request_data.as_ref(), &mut file)
                 .await
                 .map_err(internal_and_log)?;
+            if !pre_approval {
+                // Older upload waiting for approval would be shown to the owner instead of this one
+                let _ = fs::remove_file(pending_avatar_file(&user_info.uuid)).await;
+            }
         }
         Ok(())
     }
 }
 
 pub async fn user_info(
+    token: Option<Token>,
     Path(uuid): Path<Uuid>,
     State(state): State<AppState>,
 ) -> ApiResult<Json<Value>> {
     tracing::info!("Receiving profile information for {}", uuid);
 
-    let user_info = User::user_info(uuid, &state).await?;
+    let requester = token.and_then(|Token(token)| state.user_manager.get(&token).map(|user| user.uuid));
+    let user_info = User::user_info(uuid, requester, &state).await?;
 
     Ok(Json(
         serde_json::to_value(user_info).map_err(internal_and_log)?,
     ))
 }
 
-pub async fn download_avatar(Path(uuid): Path<Uuid>) -> ApiResult<Vec<u8>> {
+pub async fn download_avatar(
+    token: Option<Token>,
+    Path(uuid): Path<Uuid>,
+    State(state): State<AppState>,
+) -> ApiResult<Vec<u8>> {
+    if state.user_manager.is_banned(&uuid) {
+        return Err(ApiError::NotFound);
+    }
+    let requester = token.and_then(|Token(token)| state.user_manager.get(&token).map(|user| user.uuid));
+    let pending = if requester == Some(uuid) { fs::File::open(pending_avatar_file(&uuid)).await.ok() } else { None };
     let uuid = format_uuid(&uuid);
     tracing::info!("Requesting an avatar: {}", uuid);
-    let mut file = if let Ok(file) = fs::File::open(format!("avatars/{}.moon", uuid)).await {
+    let mut file = if let Some(file) = pending {
+        file
+    } else if let Ok(file) = fs::File::open(format!("avatars/{}.moon", uuid)).await {
         file
     } else {
         return Err(ApiError::NotFound);
@@ -147,6 +187,9 @@ pub async fn download_avatar(Path(uuid): Path<Uuid>) -> ApiResult<Vec<u8>> {
     file.read_to_end(&mut buffer)
         .await
         .map_err(internal_and_log)?;
+    if state.blocklist.is_blocked(&calculate_sha256(&buffer)) {
+        return Err(ApiError::NotFound);
+    }
     Ok(buffer)
 }
 
@@ -184,34 +227,87 @@ pub async fn delete_avatar(
             user_info.username
         );
         let avatar_file = format!("avatars/{}.moon", user_info.uuid);
-        fs::remove_file(avatar_file)
-            .await
-            .map_err(internal_and_log)?;
+        // Avatar might be only waiting for approval
+        let pending = fs::remove_file(pending_avatar_file(&user_info.uuid)).await.is_ok();
+        if let Err(err) = fs::remove_file(avatar_file).await {
+            if !pending {
+                return Err(internal_and_log(err));
+            }
+        }
         send_event(&state, &user_info.uuid).await;
     }
     // let avatar_file = format!("avatars/{}.moon",user_info.uuid);
     Ok("ok".to_string())
 }
 
+/// Upload waiting for approval
+pub fn pending_avatar_file(uuid: &Uuid) -> String {
+    format!("avatars/pending/{}.moon", format_uuid(uuid))
+}
+
 pub async fn send_event(state: &AppState, uuid: &Uuid) {
     // To user subscribers
-    if let Some(broadcast) = state.broadcasts.get(uuid) {
-        if broadcast.send(S2CMessage::Event(*uuid).to_vec()).is_err() {
-            debug!("[WebSocket] Failed to send Event! There is no one to send. UUID: {uuid}")
-        };
-    } else {
-        debug!("[WebSocket] Failed to send Event! Can't find UUID: {uuid}")
+    match state.hub.publish(uuid, S2CMessage::Event(*uuid).to_vec()) {
+        Some(0) => debug!("[WebSocket] Failed to send Event! There is no one to send. UUID: {uuid}"),
+        Some(_) => (),
+        None => debug!("[WebSocket] Failed to send Event! Can't find UUID: {uuid}"),
     };
     // To user
-    if let Some(session) = state.session.get(uuid) {
-        if session
-            .send(S2CMessage::Event(*uuid).to_vec())
-            .await
-            .is_err()
-        {
-            debug!("[WebSocket] Failed to send Event! WS doesn't connected? UUID: {uuid}")
-        };
-    } else {
-        debug!("[WebSocket] Failed to send Event! Can't find UUID: {uuid}")
+    if state.hub.send(uuid, S2CMessage::Event(*uuid).to_vec()) == 0 {
+        debug!("[WebSocket] Failed to send Event! WS doesn't connected? UUID: {uuid}")
+    };
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn pending_avatar_visibility() {
+    let state = AppState::test();
+    let (owner, other) = (Uuid::from_u128(rand::random()), Uuid::from_u128(rand::random()));
+    state.user_manager.insert(owner, "owner".to_string(), crate::auth::Userinfo { uuid: owner, ..Default::default() }).unwrap();
+    fs::create_dir_all("avatars/pending").await.unwrap();
+    let upload = |pre_approval: bool, avatar: &'static [u8]| {
+        let state = state.clone();
+        async move {
+            state.config.write().await.moderation.pre_approval = pre_approval;
+            User::upload_avatar("owner".to_string(), &state, Bytes::from_static(avatar)).await.unwrap();
+        }
     };
+    let hash = |requester| {
+        let state = state.clone();
+        async move {
+            let user = User::user_info(owner, requester, &state).await.unwrap();
+            user.equipped.first().map(|avatar| avatar["hash"].as_str().unwrap().to_string())
+        }
+    };
+
+    upload(false, b"approved").await;
+    upload(true, b"pending").await;
+    assert_eq!(hash(Some(owner)).await, Some(calculate_sha256(b"pending")), "owner sees the pending one");
+    assert_eq!(hash(Some(other)).await, Some(calculate_sha256(b"approved")));
+    assert_eq!(hash(None).await, Some(calculate_sha256(b"approved")));
+
+    upload(false, b"newer").await;
+    assert!(fs::metadata(pending_avatar_file(&owner)).await.is_err(), "replaced by the approved one");
+    assert_eq!(hash(Some(owner)).await, Some(calculate_sha256(b"newer")));
+    fs::remove_file(format!("avatars/{}.moon", format_uuid(&owner))).await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn shadow_banned_avatar() {
+    let state = AppState::test();
+    let (owner, other) = (Uuid::from_u128(rand::random()), Uuid::from_u128(rand::random()));
+    state.user_manager.insert_user(owner, crate::auth::Userinfo { uuid: owner, shadow_banned: true, ..Default::default() });
+    let avatar_file = format!("avatars/{}.moon", format_uuid(&owner));
+    fs::create_dir_all("avatars").await.unwrap();
+    fs::write(&avatar_file, b"avatar").await.unwrap();
+    let equipped = |requester| {
+        let state = state.clone();
+        async move { User::user_info(owner, requester, &state).await.unwrap().equipped.len() }
+    };
+
+    assert_eq!(equipped(Some(owner)).await, 1, "owner doesn't notice the shadow ban");
+    assert_eq!(equipped(Some(other)).await, 0);
+    assert_eq!(equipped(None).await, 0);
+    fs::remove_file(avatar_file).await.unwrap();
 }
@@ -97,4 +97,37 @@ impl<'a> C2SMessage<'a> {
     pub fn to_vec(&self) -> Vec<u8> {
         self.to_array().to_vec()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use super::*;
+
+    fn round_trip(msg: C2SMessage) {
+        assert_eq!(C2SMessage::try_from(&*msg.to_array()).unwrap(), msg);
+    }
+
+    proptest! {
+        #[test]
+        fn decode_never_panics(buf: Vec<u8>) {
+            let _ = C2SMessage::try_from(&buf[..]);
+        }
+
+        #[test]
+        fn round_trip_token(token: Vec<u8>) {
+            round_trip(C2SMessage::Token(&token));
+        }
+
+        #[test]
+        fn round_trip_ping(id: u32, sync: bool, data: Vec<u8>) {
+            round_trip(C2SMessage::Ping(id, sync, &data));
+        }
+
+        #[test]
+        fn round_trip_sub(uuid: u128) {
+            round_trip(C2SMessage::Sub(Uuid::from_u128(uuid)));
+            round_trip(C2SMessage::Unsub(Uuid::from_u128(uuid)));
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::ops::RangeInclusive;
 pub enum MessageLoadError {
     BadEnum(&'static str, RangeInclusive<usize>, usize),
     BadLength(&'static str, usize, bool, usize),
+    BadUtf8(&'static str, usize),
 }
 impl Display for MessageLoadError {
     fn fmt(&self, fmt: &mut Formatter) -> Result {
@@ -20,6 +21,10 @@ impl Display for MessageLoadError {
                 "buffer wrong size for {f}: must be {} {n} bytes, got {c}",
                 if *e { "exactly" } else { "at least" }
             ),
+            Self::BadUtf8(f, n) => write!(
+                fmt,
+                "invalid UTF-8 in {f}: only {n} bytes are valid"
+            ),
         }
     }
 }
@@ -39,4 +44,8 @@ fn message_load_error_display() {
         BadLength("bar", 17, true, 19).to_string(),
         "buffer wrong size for bar: must be exactly 17 bytes, got 19"
     );
+    assert_eq!(
+        BadUtf8("baz", 4).to_string(),
+        "invalid UTF-8 in baz: only 4 bytes are valid"
+    );
 }
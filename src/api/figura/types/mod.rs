@@ -1,9 +1,11 @@
 mod c2s;
 mod errors;
+mod nbt;
 mod s2c;
 pub mod auth;
 pub mod badges;
 
 pub use c2s::C2SMessage;
 pub use errors::MessageLoadError;
+pub use nbt::Nbt;
 pub use s2c::S2CMessage;
\ No newline at end of file
@@ -0,0 +1,213 @@
+use std::{collections::HashMap, io::Read};
+
+use flate2::read::GzDecoder;
+use thiserror::Error;
+
+/// Nested lists and compounds
+const MAX_DEPTH: usize = 512;
+/// Tags in the whole tree, so a list of millions of tiny tags can't take gigabytes in memory
+const MAX_NODES: usize = 256 * 1024;
+
+/// Named Binary Tag, Figura avatars are gzipped NBT
+#[derive(Debug, Clone, PartialEq)]
+pub enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Nbt>),
+    Compound(HashMap<String, Nbt>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+#[derive(Error, Debug)]
+pub enum NbtError {
+    #[error("can't unpack: {0}")]
+    Gzip(#[from] std::io::Error),
+    #[error("unpacked avatar is larger than {0} bytes")]
+    TooLarge(u64),
+    #[error("unexpected end of data")]
+    UnexpectedEnd,
+    #[error("unknown tag {0}")]
+    BadTag(u8),
+    #[error("nested deeper than {MAX_DEPTH}")]
+    TooDeep,
+    #[error("more than {MAX_NODES} tags")]
+    TooManyTags,
+    #[error("root tag isn't compound")]
+    NotCompound,
+}
+
+impl Nbt {
+    /// Reads gzipped NBT with compound root, unpacking at most `max_unpacked` bytes
+    pub fn from_gzip(data: &[u8], max_unpacked: u64) -> Result<Self, NbtError> {
+        let mut unpacked = Vec::new();
+        GzDecoder::new(data).take(max_unpacked.saturating_add(1)).read_to_end(&mut unpacked)?;
+        if unpacked.len() as u64 > max_unpacked {
+            return Err(NbtError::TooLarge(max_unpacked));
+        }
+        Self::from_bytes(&unpacked)
+    }
+    /// Reads uncompressed NBT with compound root
+    pub fn from_bytes(data: &[u8]) -> Result<Self, NbtError> {
+        let mut reader = Reader { data, nodes: MAX_NODES };
+        match reader.u8()? {
+            10 => {
+                reader.string()?; // Root name
+                reader.payload(10, 0)
+            },
+            _ => Err(NbtError::NotCompound),
+        }
+    }
+    pub fn get(&self, name: &str) -> Option<&Nbt> {
+        match self {
+            Nbt::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+    /// Values of compound or elements of list
+    pub fn children(&self) -> Box<dyn Iterator<Item = &Nbt> + '_> {
+        match self {
+            Nbt::Compound(tags) => Box::new(tags.values()),
+            Nbt::List(tags) => Box::new(tags.iter()),
+            _ => Box::new(std::iter::empty()),
+        }
+    }
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Nbt::ByteArray(bytes) => Some(bytes),
+            Nbt::String(string) => Some(string.as_bytes()),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    /// Tags left in the budget
+    nodes: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], NbtError> {
+        if self.data.len() < len {
+            return Err(NbtError::UnexpectedEnd);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+    fn spend(&mut self, nodes: usize) -> Result<(), NbtError> {
+        self.nodes = self.nodes.checked_sub(nodes).ok_or(NbtError::TooManyTags)?;
+        Ok(())
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], NbtError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+    fn u8(&mut self) -> Result<u8, NbtError> {
+        Ok(self.array::<1>()?[0])
+    }
+    /// Length of array or list, checked against the remaining data so a broken one can't allocate much
+    fn len(&mut self, element: usize) -> Result<usize, NbtError> {
+        let len = usize::try_from(i32::from_be_bytes(self.array()?)).unwrap_or(0);
+        if len.saturating_mul(element) > self.data.len() {
+            return Err(NbtError::UnexpectedEnd);
+        }
+        Ok(len)
+    }
+    fn string(&mut self) -> Result<String, NbtError> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        // Java's modified UTF-8 differs only for rare characters
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+    fn payload(&mut self, tag: u8, depth: usize) -> Result<Nbt, NbtError> {
+        if depth > MAX_DEPTH {
+            return Err(NbtError::TooDeep);
+        }
+        self.spend(1)?;
+        Ok(match tag {
+            1 => Nbt::Byte(i8::from_be_bytes(self.array()?)),
+            2 => Nbt::Short(i16::from_be_bytes(self.array()?)),
+            3 => Nbt::Int(i32::from_be_bytes(self.array()?)),
+            4 => Nbt::Long(i64::from_be_bytes(self.array()?)),
+            5 => Nbt::Float(f32::from_be_bytes(self.array()?)),
+            6 => Nbt::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let len = self.len(1)?;
+                Nbt::ByteArray(self.take(len)?.to_vec())
+            },
+            8 => Nbt::String(self.string()?),
+            9 => {
+                let element = self.u8()?;
+                let len = self.len(1)?;
+                if element == 0 && len != 0 {
+                    return Err(NbtError::BadTag(0));
+                }
+                // Fails before reading the elements one by one
+                if len > self.nodes {
+                    return Err(NbtError::TooManyTags);
+                }
+                let list = (0..len).map(|_| self.payload(element, depth + 1)).collect::<Result<_, _>>()?;
+                Nbt::List(list)
+            },
+            10 => {
+                let mut tags = HashMap::new();
+                loop {
+                    let tag = self.u8()?;
+                    if tag == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    tags.insert(name, self.payload(tag, depth + 1)?);
+                }
+                Nbt::Compound(tags)
+            },
+            11 => {
+                let len = self.len(4)?;
+                Nbt::IntArray((0..len).map(|_| self.array().map(i32::from_be_bytes)).collect::<Result<_, _>>()?)
+            },
+            12 => {
+                let len = self.len(8)?;
+                Nbt::LongArray((0..len).map(|_| self.array().map(i64::from_be_bytes)).collect::<Result<_, _>>()?)
+            },
+            tag => return Err(NbtError::BadTag(tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn nbt_bombs() {
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(data).unwrap();
+        gzip.finish().unwrap()
+    }
+    // Root compound with a list of `count` tags, each takes `size` bytes
+    fn list_bomb(element: u8, count: i32, size: usize) -> Vec<u8> {
+        let mut nbt = vec![10, 0, 0, 9, 0, 1, b'l', element];
+        nbt.extend(count.to_be_bytes());
+        nbt.resize(nbt.len() + count as usize * size, 0);
+        nbt.extend([0]);
+        gzip(&nbt)
+    }
+
+    let bytes = list_bomb(1, 32 * 1024 * 1024, 1);
+    let compounds = list_bomb(10, 32 * 1024 * 1024, 1);
+    assert!(bytes.len() < 100_000 && compounds.len() < 100_000);
+    assert!(matches!(Nbt::from_gzip(&bytes, 64 * 1024 * 1024), Err(NbtError::TooManyTags)));
+    assert!(matches!(Nbt::from_gzip(&compounds, 64 * 1024 * 1024), Err(NbtError::TooManyTags)));
+    assert!(matches!(Nbt::from_gzip(&bytes, 1024 * 1024), Err(NbtError::TooLarge(_))));
+    // Elements are counted one by one too, when the list length is within the budget
+    let inner = [1, 0, 0, 0, 1, 0]; // List with a single byte
+    let many_lists = gzip(&[[10, 0, 0, 9, 0, 1, b'l', 9].as_slice(), &200_000i32.to_be_bytes(), &inner.repeat(200_000), &[0]].concat());
+    assert!(matches!(Nbt::from_gzip(&many_lists, 64 * 1024 * 1024), Err(NbtError::TooManyTags)));
+    assert!(Nbt::from_gzip(&list_bomb(1, 1000, 1), 1024 * 1024).is_ok());
+}
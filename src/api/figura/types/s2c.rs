@@ -48,14 +48,34 @@ impl<'a> TryFrom<&'a [u8]> for S2CMessage<'a> {
                         Err(BadLength("S2CMessage::Event", 17, true, buf.len()))
                     }
                 }
-                3 => todo!(),
-                4 => todo!(),
-                5 => todo!(),
+                3 => {
+                    if buf.len() >= 2 {
+                        // Header and optional description are separated by NUL
+                        let mut parts = buf[2..].splitn(2, |&b| b == 0);
+                        let header = from_utf8("S2CMessage::Toast", parts.next().unwrap_or_default())?;
+                        let description = parts.next().map(|d| from_utf8("S2CMessage::Toast", d)).transpose()?;
+                        Ok(Toast(buf[1], header, description))
+                    } else {
+                        Err(BadLength("S2CMessage::Toast", 2, false, buf.len()))
+                    }
+                }
+                4 => Ok(Chat(from_utf8("S2CMessage::Chat", &buf[1..])?)),
+                5 => {
+                    if buf.len() == 2 {
+                        Ok(Notice(buf[1]))
+                    } else {
+                        Err(BadLength("S2CMessage::Notice", 2, true, buf.len()))
+                    }
+                }
                 a => Err(BadEnum("S2CMessage.type", 0..=5, a.into())),
             }
         }
     }
 }
+fn from_utf8<'a>(field: &'static str, buf: &'a [u8]) -> Result<&'a str, MessageLoadError> {
+    std::str::from_utf8(buf).map_err(|e| MessageLoadError::BadUtf8(field, e.valid_up_to()))
+}
+
 impl<'a> From<S2CMessage<'a>> for Box<[u8]> {
     fn from(val: S2CMessage<'a>) -> Self {
         use std::iter::once;
@@ -91,3 +111,59 @@ impl<'a> S2CMessage<'a> {
         self.to_array().to_vec()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use super::*;
+    use S2CMessage::*;
+
+    fn round_trip(msg: S2CMessage) {
+        assert_eq!(S2CMessage::try_from(&*msg.to_array()).unwrap(), msg);
+    }
+
+    proptest! {
+        #[test]
+        fn decode_never_panics(buf: Vec<u8>) {
+            let _ = S2CMessage::try_from(&buf[..]);
+        }
+
+        #[test]
+        fn round_trip_ping(uuid: u128, id: u32, sync: bool, data: Vec<u8>) {
+            round_trip(Ping(Uuid::from_u128(uuid), id, sync, &data));
+        }
+
+        #[test]
+        fn round_trip_event(uuid: u128) {
+            round_trip(Event(Uuid::from_u128(uuid)));
+        }
+
+        #[test]
+        fn round_trip_toast(kind: u8, header in "[^\\x00]*", description: Option<String>) {
+            round_trip(Toast(kind, &header, description.as_deref()));
+        }
+
+        #[test]
+        fn round_trip_chat(text: String) {
+            round_trip(Chat(&text));
+        }
+
+        #[test]
+        fn round_trip_notice(kind: u8) {
+            round_trip(Notice(kind));
+        }
+    }
+
+    #[test]
+    fn round_trip_auth() {
+        round_trip(Auth);
+    }
+
+    #[test]
+    fn toast_bad_utf8() {
+        assert!(matches!(
+            S2CMessage::try_from(&[3, 0, b'o', b'k', 0, 0xff][..]),
+            Err(MessageLoadError::BadUtf8("S2CMessage::Toast", 0))
+        ));
+    }
+}
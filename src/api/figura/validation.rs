@@ -0,0 +1,271 @@
+use std::process::Stdio;
+
+use axum::body::Bytes;
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use super::types::Nbt;
+use crate::{state::{ValidationConfig, ValidationHook}, ApiError, ApiResult, AppState};
+
+/// Unpacked avatar can be this many times larger than the upload limit
+const UNPACK_RATIO: u64 = 32;
+
+/// Checks the uploaded avatar with the rules and the hook from the config
+pub async fn validate_avatar(state: &AppState, owner: Uuid, avatar: &Bytes) -> ApiResult<()> {
+    let (config, max_unpacked) = {
+        let config = state.config.read().await;
+        (config.validation.clone(), config.limitations.max_avatar_size.saturating_mul(UNPACK_RATIO))
+    };
+    if !config.has_rules() && config.hook.is_none() {
+        return Ok(());
+    }
+    let failed = {
+        let (config, avatar) = (config.clone(), avatar.clone());
+        tokio::task::spawn_blocking(move || check_rules(&config, max_unpacked, &owner, &avatar)).await.map_err(|err| {
+            error!("Avatar validation panicked: {err}");
+            ApiError::Internal
+        })?
+    };
+    if !failed.is_empty() {
+        warn!("Avatar of {owner} failed validation: {}", failed.join(", "));
+        return Err(ApiError::InvalidAvatar(failed));
+    }
+    if let Some(hook) = &config.hook {
+        if !run_hook(hook, &owner, avatar).await {
+            warn!("Avatar of {owner} was denied by the validation hook");
+            return Err(ApiError::InvalidAvatar(vec!["hook"]));
+        }
+    }
+    Ok(())
+}
+
+/// Names of the failed rules
+fn check_rules(config: &ValidationConfig, max_unpacked: u64, owner: &Uuid, avatar: &[u8]) -> Vec<&'static str> {
+    if !config.has_rules() {
+        return Vec::new();
+    }
+    let nbt = match Nbt::from_gzip(avatar, max_unpacked) {
+        Ok(nbt) => nbt,
+        Err(err) => {
+            debug!("Avatar of {owner} isn't valid NBT: {err}");
+            return vec!["nbt"];
+        },
+    };
+    let scripts: Vec<(&String, &[u8])> = match nbt.get("scripts") {
+        Some(Nbt::Compound(scripts)) => scripts.iter().filter_map(|(name, script)| Some((name, script.as_bytes()?))).collect(),
+        _ => Vec::new(),
+    };
+    let mut failed = Vec::new();
+
+    let script_size: u64 = scripts.iter().map(|(_, script)| script.len() as u64).sum();
+    if config.max_script_size != 0 && script_size > config.max_script_size {
+        debug!("Scripts of {owner} take {script_size} bytes");
+        failed.push("maxScriptSize");
+    }
+
+    if config.max_texture_size != 0 {
+        let textures = nbt.get("textures").and_then(|textures| textures.get("src")).into_iter().flat_map(Nbt::children);
+        let largest = textures.filter_map(|texture| png_size(texture.as_bytes()?)).max_by_key(|&(width, height)| width.max(height));
+        if let Some((width, height)) = largest.filter(|&(width, height)| width.max(height) > config.max_texture_size) {
+            debug!("Avatar of {owner} has {width}x{height} texture");
+            failed.push("maxTextureSize");
+        }
+    }
+
+    if config.max_elements != 0 {
+        let elements = nbt.get("models").map_or(0, count_elements);
+        if elements > config.max_elements {
+            debug!("Models of {owner} have {elements} elements");
+            failed.push("maxElements");
+        }
+    }
+
+    let forbidden = config.forbidden_patterns.iter().find_map(|regex| {
+        let (name, _) = scripts.iter().find(|(_, script)| regex.is_match(&String::from_utf8_lossy(script)))?;
+        Some((regex, name))
+    });
+    if let Some((regex, name)) = forbidden {
+        debug!("Script {name} of {owner} matches forbidden pattern {regex}");
+        failed.push("forbiddenPatterns");
+    }
+
+    failed
+}
+
+/// Model part with its children, every part is a group, cube or mesh
+fn count_elements(part: &Nbt) -> u64 {
+    let children = match part.get("chld") {
+        Some(Nbt::List(children)) => children.iter().map(count_elements).sum(),
+        _ => 0,
+    };
+    1 + children
+}
+
+/// Width and height from the IHDR chunk
+fn png_size(png: &[u8]) -> Option<(u32, u32)> {
+    if png.len() < 24 || !png.starts_with(b"\x89PNG\r\n\x1a\n") || &png[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(png[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(png[20..24].try_into().ok()?);
+    Some((width, height))
+}
+
+/// Returns true if the avatar is allowed
+async fn run_hook(hook: &ValidationHook, owner: &Uuid, avatar: &Bytes) -> bool {
+    let result = tokio::time::timeout(hook.timeout(), async {
+        if !hook.command.is_empty() && !run_command(&hook.command, owner, avatar).await? {
+            return Ok(false);
+        }
+        match &hook.url {
+            Some(url) => send_request(url, owner, avatar).await,
+            None => Ok(true),
+        }
+    }).await;
+    match result {
+        Ok(Ok(allowed)) => allowed,
+        Ok(Err(err)) => {
+            error!("Validation hook failed: {err}");
+            hook.fail_open
+        },
+        Err(_) => {
+            error!("Validation hook timed out");
+            hook.fail_open
+        },
+    }
+}
+
+async fn run_command(command: &[String], owner: &Uuid, avatar: &[u8]) -> anyhow::Result<bool> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .env("SCULPTOR_AVATAR_OWNER", owner.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Validator may exit without reading everything
+    if let Err(err) = stdin.write_all(avatar).await {
+        debug!("Validation hook didn't read the avatar: {err}");
+    }
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(if output.stdout.is_empty() { &output.stderr } else { &output.stdout }).trim().to_string();
+        debug!("Validation hook denied the avatar of {owner} with {}: {message}", output.status);
+    }
+    Ok(output.status.success())
+}
+
+async fn send_request(url: &str, owner: &Uuid, avatar: &Bytes) -> anyhow::Result<bool> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .header("X-Avatar-Owner", owner.to_string())
+        .body(avatar.clone())
+        .send()
+        .await?;
+    let status = response.status();
+    if status.is_server_error() {
+        anyhow::bail!("hook responded with {status}");
+    }
+    if !status.is_success() {
+        debug!("Validation hook denied the avatar of {owner} with {status}: {}", response.text().await.unwrap_or_default().trim());
+    }
+    Ok(status.is_success())
+}
+
+#[cfg(test)]
+#[test]
+fn avatar_rules() {
+    use std::{collections::HashMap, io::Write};
+    use crate::state::Patterns;
+
+    // NBT writer for the compound and byte arrays used below
+    fn compound(out: &mut Vec<u8>, tags: &[(&str, &[u8])]) {
+        for (name, data) in tags {
+            out.push(7);
+            out.extend((name.len() as u16).to_be_bytes());
+            out.extend(name.as_bytes());
+            out.extend((data.len() as i32).to_be_bytes());
+            out.extend(*data);
+        }
+        out.push(0);
+    }
+    let png = [b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".as_slice(), &64u32.to_be_bytes(), &32u32.to_be_bytes()].concat();
+    let mut nbt = vec![10, 0, 0];
+    nbt.extend([10, 0, 7]);
+    nbt.extend(b"scripts");
+    compound(&mut nbt, &[("main", b"print('hi') loadstring(x)")]);
+    nbt.extend([10, 0, 8]);
+    nbt.extend(b"textures");
+    nbt.extend([10, 0, 3]);
+    nbt.extend(b"src");
+    compound(&mut nbt, &[("skin", &png)]);
+    nbt.push(0);
+    nbt.push(0);
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&nbt).unwrap();
+    let avatar = gzip.finish().unwrap();
+
+    let parsed = Nbt::from_gzip(&avatar, 1024 * 1024).unwrap();
+    assert_eq!(parsed.get("scripts"), Some(&Nbt::Compound(HashMap::from([("main".to_string(), Nbt::ByteArray(b"print('hi') loadstring(x)".to_vec()))]))));
+    assert_eq!(png_size(&png), Some((64, 32)));
+
+    let owner = Uuid::nil();
+    let config = ValidationConfig { max_script_size: 1024, max_texture_size: 64, max_elements: 1, ..Default::default() };
+    assert!(check_rules(&config, 1024 * 1024, &owner, &avatar).is_empty());
+    let config = ValidationConfig {
+        max_script_size: 10,
+        max_texture_size: 16,
+        forbidden_patterns: Patterns::new(&[r"loadstring\s*\("]).unwrap(),
+        ..Default::default()
+    };
+    assert_eq!(check_rules(&config, 1024 * 1024, &owner, &avatar), ["maxScriptSize", "maxTextureSize", "forbiddenPatterns"]);
+    assert_eq!(check_rules(&config, 1024 * 1024, &owner, &nbt), ["nbt"], "not gzipped");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn validation_hook() {
+    use axum::{http::StatusCode, routing::post, Router};
+
+    let avatar = Bytes::from_static(b"avatar");
+    let owner = Uuid::nil();
+    let sh = |script: &str| vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+    let hook = |command: Vec<String>, url: Option<String>| ValidationHook { command, url, timeout: 1, ..Default::default() };
+
+    // Command
+    assert!(run_hook(&hook(sh(r#"[ "$(cat)" = avatar ] && [ "$SCULPTOR_AVATAR_OWNER" = 00000000-0000-0000-0000-000000000000 ]"#), None), &owner, &avatar).await);
+    assert!(!run_hook(&hook(sh("exit 1"), None), &owner, &avatar).await);
+    assert!(!run_hook(&hook(sh("sleep 5"), None), &owner, &avatar).await, "timeout");
+    assert!(!run_hook(&hook(vec!["/nonexistent".to_string()], None), &owner, &avatar).await);
+    let fail_open = |command| ValidationHook { fail_open: true, ..hook(command, None) };
+    assert!(run_hook(&fail_open(sh("sleep 5")), &owner, &avatar).await, "fail open on timeout");
+    assert!(run_hook(&fail_open(vec!["/nonexistent".to_string()]), &owner, &avatar).await, "fail open on error");
+    assert!(!run_hook(&fail_open(sh("exit 1")), &owner, &avatar).await, "denial isn't a failure");
+
+    // HTTP
+    let app = Router::new()
+        .route("/allow", post(|body: Bytes| async move { if body == "avatar" { StatusCode::OK } else { StatusCode::FORBIDDEN } }))
+        .route("/deny", post(|| async { StatusCode::FORBIDDEN }))
+        .route("/broken", post(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+        .route("/slow", post(|| async { tokio::time::sleep(std::time::Duration::from_secs(5)).await; StatusCode::OK }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let url = |path: &str| Some(format!("http://{addr}/{path}"));
+
+    assert!(run_hook(&hook(Vec::new(), url("allow")), &owner, &avatar).await);
+    assert!(!run_hook(&hook(Vec::new(), url("deny")), &owner, &avatar).await);
+    assert!(!run_hook(&hook(Vec::new(), url("broken")), &owner, &avatar).await);
+    assert!(!run_hook(&hook(Vec::new(), url("slow")), &owner, &avatar).await, "timeout");
+    let fail_open = |path| ValidationHook { fail_open: true, ..hook(Vec::new(), url(path)) };
+    assert!(run_hook(&fail_open("broken"), &owner, &avatar).await, "fail open on 5xx");
+    assert!(run_hook(&fail_open("slow"), &owner, &avatar).await, "fail open on timeout");
+    assert!(!run_hook(&fail_open("deny"), &owner, &avatar).await, "denial isn't a failure");
+    // Both run, the command first
+    assert!(!run_hook(&hook(sh("exit 1"), url("allow")), &owner, &avatar).await);
+    assert!(!run_hook(&hook(sh("exit 0"), url("deny")), &owner, &avatar).await);
+}
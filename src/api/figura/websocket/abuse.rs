@@ -0,0 +1,173 @@
+use std::{collections::HashSet, time::Duration};
+
+use dashmap::DashMap;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::state::AbuseConfig;
+
+/// Suspicious behaviour found by a heuristic
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Detection {
+    pub heuristic: &'static str,
+    /// What was measured
+    pub evidence: String,
+}
+
+/// Measures a single connection over fixed windows, detects at most once per window for each heuristic
+pub(super) struct AbuseDetector {
+    config: AbuseConfig,
+    started: Instant,
+    pings: u64,
+    oversized: u32,
+    largest: usize,
+    churn: u64,
+    /// Unsubscribed in this window, Sub of them again is churn
+    unsubscribed: HashSet<Uuid>,
+}
+
+impl AbuseDetector {
+    pub fn new(config: &AbuseConfig) -> Self {
+        Self {
+            config: config.clone(),
+            started: Instant::now(),
+            pings: 0,
+            oversized: 0,
+            largest: 0,
+            churn: 0,
+            unsubscribed: HashSet::new(),
+        }
+    }
+    /// Called for every ping, even for dropped by the rate limiter
+    pub fn ping(&mut self, size: usize) -> Option<Detection> {
+        if !self.config.enabled {
+            return None;
+        }
+        self.roll();
+        self.pings += 1;
+        let max_pings = self.config.max_ping_rate * self.config.window().as_secs();
+        if self.config.max_ping_rate != 0 && self.pings > max_pings {
+            let evidence = format!("{} pings in {:.1}s, limit is {max_pings}", self.pings, self.started.elapsed().as_secs_f32());
+            self.pings = 0;
+            return Some(Detection { heuristic: "ping rate", evidence });
+        }
+        if self.config.oversized_ping != 0 && size > self.config.oversized_ping {
+            self.oversized += 1;
+            self.largest = self.largest.max(size);
+            if self.oversized > self.config.max_oversized {
+                let evidence = format!("{} pings over {} bytes in {:.1}s, the largest is {} bytes",
+                    self.oversized, self.config.oversized_ping, self.started.elapsed().as_secs_f32(), self.largest);
+                self.oversized = 0;
+                self.largest = 0;
+                return Some(Detection { heuristic: "oversized pings", evidence });
+            }
+        }
+        None
+    }
+    /// Called for every Unsub
+    pub fn unsub(&mut self, uuid: Uuid) {
+        if !self.config.enabled || self.config.max_sub_churn == 0 {
+            return;
+        }
+        self.roll();
+        self.unsubscribed.insert(uuid);
+    }
+    /// Called for every Sub, only subscribing again after Unsub is counted,
+    /// so subscribing to everyone around on join isn't churn
+    pub fn sub(&mut self, uuid: Uuid) -> Option<Detection> {
+        if !self.config.enabled || self.config.max_sub_churn == 0 {
+            return None;
+        }
+        self.roll();
+        if !self.unsubscribed.remove(&uuid) {
+            return None;
+        }
+        self.churn += 1;
+        let max_churn = self.config.max_sub_churn * self.config.window().as_secs();
+        if self.churn > max_churn {
+            let evidence = format!("{} re-subscriptions in {:.1}s, limit is {max_churn}", self.churn, self.started.elapsed().as_secs_f32());
+            self.churn = 0;
+            return Some(Detection { heuristic: "subscription churn", evidence });
+        }
+        None
+    }
+    /// Starts a new window if the current one is over
+    fn roll(&mut self) {
+        if self.started.elapsed() >= self.config.window() {
+            *self = Self::new(&self.config);
+        }
+    }
+}
+
+/// Punishment for a strike
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AbuseAction {
+    Warn,
+    Mute,
+    Disconnect,
+    Ban,
+}
+
+impl AbuseAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AbuseAction::Warn => "warn",
+            AbuseAction::Mute => "mute",
+            AbuseAction::Disconnect => "disconnect",
+            AbuseAction::Ban => "ban",
+        }
+    }
+}
+
+/// Strikes of users, kept between connections so reconnecting doesn't reset the escalation
+#[derive(Debug, Default)]
+pub struct AbuseTracker {
+    /// Number of strikes and time of the last one
+    strikes: DashMap<Uuid, (u32, Instant)>,
+}
+
+impl AbuseTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a strike and returns the action for it
+    pub fn strike(&self, uuid: Uuid, forgive_after: Duration) -> AbuseAction {
+        self.strikes.retain(|_, (_, last)| last.elapsed() < forgive_after);
+        let mut strikes = self.strikes.entry(uuid).or_insert((0, Instant::now()));
+        *strikes = (strikes.0 + 1, Instant::now());
+        match strikes.0 {
+            1 => AbuseAction::Warn,
+            2 => AbuseAction::Mute,
+            3 => AbuseAction::Disconnect,
+            _ => AbuseAction::Ban,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn abuse_detection() {
+    let config = AbuseConfig { enabled: true, window: 1, max_ping_rate: 4, oversized_ping: 10, max_oversized: 1, max_sub_churn: 2, ..Default::default() };
+    let mut detector = AbuseDetector::new(&config);
+    assert!((0..4).all(|_| detector.ping(1).is_none()));
+    assert_eq!(detector.ping(1).unwrap().heuristic, "ping rate");
+    assert!(detector.ping(11).is_none());
+    assert_eq!(detector.ping(12).unwrap().heuristic, "oversized pings");
+    // Subscribing to many players at once isn't churn
+    assert!((0..1000).all(|i| detector.sub(Uuid::from_u128(i)).is_none()));
+    let resub = |detector: &mut AbuseDetector| {
+        detector.unsub(Uuid::nil());
+        detector.sub(Uuid::nil())
+    };
+    assert!((0..2).all(|_| resub(&mut detector).is_none()));
+    assert_eq!(resub(&mut detector).unwrap().heuristic, "subscription churn");
+
+    let mut disabled = AbuseDetector::new(&AbuseConfig { enabled: false, ..config });
+    assert!((0..100).all(|_| disabled.ping(100).is_none() && resub(&mut disabled).is_none()));
+
+    let tracker = AbuseTracker::new();
+    let (uuid, forgive) = (Uuid::nil(), Duration::from_secs(60));
+    let actions: Vec<_> = (0..5).map(|_| tracker.strike(uuid, forgive)).collect();
+    assert_eq!(actions, [AbuseAction::Warn, AbuseAction::Mute, AbuseAction::Disconnect, AbuseAction::Ban, AbuseAction::Ban]);
+    assert_eq!(tracker.strike(uuid, Duration::ZERO), AbuseAction::Warn, "strikes are forgiven");
+}
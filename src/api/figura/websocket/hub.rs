@@ -0,0 +1,302 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex},
+};
+
+use dashmap::DashMap;
+use tokio::sync::Notify;
+use tracing::debug;
+use uuid::Uuid;
+
+use super::CloseReason;
+
+/// Encoded `S2CMessage` shared between all receivers
+pub type Frame = Arc<[u8]>;
+
+/// What the connection should do next
+#[derive(Debug)]
+pub(super) enum Outgoing {
+    Frame(Frame),
+    /// Queued frames are sent before closing
+    Close(CloseReason),
+}
+
+/// Outbound queue of a single WebSocket connection.
+/// Slow consumer policy: when the queue is full the oldest frame is dropped and counted.
+#[derive(Debug)]
+pub struct Outbox {
+    id: u64,
+    capacity: usize,
+    queue: Mutex<VecDeque<Frame>>,
+    /// Set once, no frames are accepted after it
+    close: Mutex<Option<CloseReason>>,
+    notify: Notify,
+    dropped: AtomicU64,
+}
+
+impl Outbox {
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+    /// Returns false if the oldest frame was dropped to make room
+    fn push(&self, frame: Frame) -> bool {
+        if self.close.lock().unwrap().is_some() {
+            return true;
+        }
+        let mut queue = self.queue.lock().unwrap();
+        let mut fits = true;
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            fits = false;
+        }
+        queue.push_back(frame);
+        drop(queue);
+        self.notify.notify_one();
+        fits
+    }
+    fn close(&self, reason: CloseReason) {
+        self.close.lock().unwrap().get_or_insert(reason);
+        self.notify.notify_one();
+    }
+    /// Takes queued frames without waiting
+    #[cfg(test)]
+    pub fn drain(&self) -> Vec<Vec<u8>> {
+        self.queue.lock().unwrap().drain(..).map(|frame| frame.to_vec()).collect()
+    }
+    /// Waits for the next frame or close, cancel safe
+    pub(super) async fn pop(&self) -> Outgoing {
+        loop {
+            if let Some(frame) = self.queue.lock().unwrap().pop_front() {
+                return Outgoing::Frame(frame);
+            }
+            if let Some(reason) = self.close.lock().unwrap().take() {
+                return Outgoing::Close(reason);
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// Pings of a single user, exists while the user is connected or has subscribers
+#[derive(Debug, Default)]
+struct Channel {
+    /// Connections of the publisher
+    publishers: usize,
+    /// Subscribers by connection id
+    subscribers: HashMap<u64, Arc<Outbox>>,
+}
+
+impl Channel {
+    fn is_unused(&self) -> bool {
+        self.publishers == 0 && self.subscribers.is_empty()
+    }
+}
+
+/// Routes frames to WebSocket connections: to the user's own connections and to subscribers of the user's pings
+#[derive(Debug, Default)]
+pub struct Hub {
+    next_id: AtomicU64,
+    /// Every open connection, including unauthenticated ones
+    connections: DashMap<u64, Arc<Outbox>>,
+    /// Connections of the user
+    sessions: DashMap<Uuid, Vec<Arc<Outbox>>>,
+    channels: DashMap<Uuid, Channel>,
+    /// Frames dropped for slow consumers
+    dropped: AtomicU64,
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Creates queue for a new connection
+    pub fn outbox(&self, capacity: usize) -> Arc<Outbox> {
+        let outbox = Arc::new(Outbox {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            capacity: capacity.max(1),
+            queue: Mutex::new(VecDeque::new()),
+            close: Mutex::new(None),
+            notify: Notify::new(),
+            dropped: AtomicU64::new(0),
+        });
+        self.connections.insert(outbox.id, Arc::clone(&outbox));
+        outbox
+    }
+    /// Forgets closed connection
+    pub fn remove(&self, outbox: &Outbox) {
+        self.connections.remove(&outbox.id);
+    }
+    /// Closes every connection after sending the queued frames, returns the number of connections
+    pub fn close_all(&self, code: u16, reason: &'static str) -> usize {
+        for connection in self.connections.iter() {
+            connection.close(CloseReason::new(code, reason));
+        }
+        self.connections.len()
+    }
+    /// Number of users with at least one connection
+    pub fn sessions(&self) -> usize {
+        self.sessions.len()
+    }
+    /// Number of active ping channels
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+    /// Users with at least one connection
+    pub fn online(&self) -> Vec<Uuid> {
+        self.sessions.iter().map(|sessions| *sessions.key()).collect()
+    }
+    pub fn is_online(&self, uuid: &Uuid) -> bool {
+        self.sessions.contains_key(uuid)
+    }
+    /// Registers authenticated connection of the user
+    pub fn join(&self, uuid: Uuid, outbox: &Arc<Outbox>) {
+        self.sessions.entry(uuid).or_default().push(Arc::clone(outbox));
+        self.channels.entry(uuid).or_default().publishers += 1;
+    }
+    /// Removes connection and all its subscriptions
+    pub fn leave<'a>(&self, uuid: Uuid, outbox: &Outbox, subscriptions: impl IntoIterator<Item = &'a Uuid>) {
+        for publisher in subscriptions {
+            self.unsubscribe(*publisher, outbox);
+        }
+        if let Some(mut sessions) = self.sessions.get_mut(&uuid) {
+            sessions.retain(|session| session.id != outbox.id);
+        }
+        self.sessions.remove_if(&uuid, |_, sessions| sessions.is_empty());
+        if let Some(mut channel) = self.channels.get_mut(&uuid) {
+            channel.publishers = channel.publishers.saturating_sub(1);
+        }
+        self.channels.remove_if(&uuid, |_, channel| channel.is_unused());
+    }
+    pub fn subscribe(&self, publisher: Uuid, outbox: &Arc<Outbox>) {
+        self.channels.entry(publisher).or_default().subscribers.insert(outbox.id, Arc::clone(outbox));
+    }
+    pub fn unsubscribe(&self, publisher: Uuid, outbox: &Outbox) {
+        if let Some(mut channel) = self.channels.get_mut(&publisher) {
+            channel.subscribers.remove(&outbox.id);
+        }
+        self.channels.remove_if(&publisher, |_, channel| channel.is_unused());
+    }
+    /// Sends frame to subscribers of the user, returns the number of receivers or None if the user is unknown
+    pub fn publish(&self, publisher: &Uuid, frame: Vec<u8>) -> Option<usize> {
+        let channel = self.channels.get(publisher)?;
+        let frame: Frame = frame.into();
+        for subscriber in channel.subscribers.values() {
+            self.push(subscriber, Arc::clone(&frame));
+        }
+        Some(channel.subscribers.len())
+    }
+    /// Sends frame to every connection of the user, returns the number of receivers
+    pub fn send(&self, uuid: &Uuid, frame: Vec<u8>) -> usize {
+        let Some(sessions) = self.sessions.get(uuid) else { return 0 };
+        let frame: Frame = frame.into();
+        for session in sessions.iter() {
+            self.push(session, Arc::clone(&frame));
+        }
+        sessions.len()
+    }
+    /// Closes every connection of the user after sending the queued frames, returns the number of connections
+    pub fn close(&self, uuid: &Uuid, code: u16, reason: &'static str) -> usize {
+        let Some(sessions) = self.sessions.get(uuid) else { return 0 };
+        for session in sessions.iter() {
+            session.close(CloseReason::new(code, reason));
+        }
+        sessions.len()
+    }
+    /// Sends frame to every connection, returns the number of receivers
+    pub fn send_all(&self, frame: Vec<u8>) -> usize {
+        let frame: Frame = frame.into();
+        let mut count = 0;
+        for sessions in self.sessions.iter() {
+            for session in sessions.iter() {
+                self.push(session, Arc::clone(&frame));
+                count += 1;
+            }
+        }
+        count
+    }
+    /// Sends frame to a single connection
+    pub fn send_to(&self, outbox: &Outbox, frame: Vec<u8>) {
+        self.push(outbox, frame.into());
+    }
+    fn push(&self, outbox: &Outbox, frame: Frame) {
+        if !outbox.push(frame) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!("[WebSocket] Outbound queue of connection {} is full! Dropped the oldest frame", outbox.id);
+        }
+    }
+}
+
+#[cfg(test)]
+async fn next_frame(outbox: &Outbox) -> Vec<u8> {
+    match outbox.pop().await {
+        Outgoing::Frame(frame) => frame.to_vec(),
+        Outgoing::Close(reason) => panic!("unexpected close: {reason:?}"),
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn hub_fan_out() {
+    let hub = Hub::new();
+    let (alice, bob) = (Uuid::from_u128(1), Uuid::from_u128(2));
+    let alice_conn = hub.outbox(2);
+    let bob_conn = hub.outbox(2);
+    hub.join(alice, &alice_conn);
+    hub.join(bob, &bob_conn);
+    hub.subscribe(alice, &bob_conn);
+
+    assert_eq!(hub.publish(&alice, vec![1]), Some(1));
+    assert_eq!(hub.publish(&bob, vec![2]), Some(0));
+    assert_eq!(hub.publish(&Uuid::nil(), vec![3]), None);
+    assert_eq!(next_frame(&bob_conn).await, [1]);
+
+    // Slow consumer loses the oldest frames but stays subscribed
+    for i in 0..5 {
+        hub.publish(&alice, vec![i]);
+    }
+    assert_eq!(bob_conn.dropped(), 3);
+    assert_eq!(hub.dropped(), 3);
+    assert_eq!(next_frame(&bob_conn).await, [3]);
+    assert_eq!(next_frame(&bob_conn).await, [4]);
+    assert_eq!(hub.publish(&alice, vec![5]), Some(1));
+
+    hub.leave(bob, &bob_conn, &[alice]);
+    assert_eq!(hub.publish(&alice, vec![6]), Some(0));
+    assert!(!hub.is_online(&bob));
+    assert_eq!(hub.send(&alice, vec![7]), 1);
+    assert_eq!(next_frame(&alice_conn).await, [7]);
+
+    // Queued frames go before close, nothing is accepted after it
+    hub.send(&alice, vec![8]);
+    assert_eq!(hub.close_all(1012, "Server restarting"), 2);
+    hub.send(&alice, vec![9]);
+    assert_eq!(next_frame(&alice_conn).await, [8]);
+    assert!(matches!(alice_conn.pop().await, Outgoing::Close(CloseReason { code: 1012, .. })));
+}
+
+#[cfg(test)]
+#[test]
+fn hub_channel_lifecycle() {
+    let hub = Hub::new();
+    let (alice, bob) = (Uuid::from_u128(1), Uuid::from_u128(2));
+    let alice_conn = hub.outbox(1);
+    let bob_conn = hub.outbox(1);
+
+    // Subscription to an offline user keeps the channel until unsubscribed
+    hub.subscribe(bob, &alice_conn);
+    assert_eq!(hub.channels(), 1);
+    hub.unsubscribe(bob, &alice_conn);
+    assert_eq!(hub.channels(), 0);
+
+    hub.join(alice, &alice_conn);
+    hub.join(bob, &bob_conn);
+    hub.subscribe(alice, &bob_conn);
+    hub.leave(alice, &alice_conn, &[]);
+    assert_eq!(hub.channels(), 2, "Alice still has a subscriber");
+    hub.leave(bob, &bob_conn, &[alice]);
+    assert_eq!(hub.channels(), 0);
+}
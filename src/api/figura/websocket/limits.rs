@@ -0,0 +1,192 @@
+use std::{
+    net::IpAddr,
+    sync::{atomic::{AtomicUsize, Ordering}, Arc},
+};
+
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::{state::{PingPolicy, RateLimits, WebSocketConfig}, utils::TokenBucket};
+
+/// What to do with the received ping
+#[derive(Debug, PartialEq)]
+pub(super) enum PingVerdict {
+    Allow,
+    Drop,
+    /// Drop and warn the sender
+    Warn,
+    /// Too many violations
+    Close,
+}
+
+/// Enforces advertised ping limits for a single connection
+pub(super) struct PingLimiter {
+    rate: TokenBucket,
+    size: TokenBucket,
+    policy: PingPolicy,
+    close_after: u32,
+    violations: u32,
+    warned: bool,
+}
+
+impl PingLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        Self {
+            rate: TokenBucket::new(limits.ping_rate, limits.ping_rate),
+            size: TokenBucket::new(limits.ping_size, limits.ping_size),
+            policy: limits.ping_policy,
+            close_after: limits.ping_close_after,
+            violations: 0,
+            warned: false,
+        }
+    }
+    pub fn check(&mut self, size: usize) -> PingVerdict {
+        // Rejected ping doesn't spend tokens of the other bucket
+        if self.rate.can_take(1) && self.size.can_take(size as u64) {
+            self.rate.try_take(1);
+            self.size.try_take(size as u64);
+            self.warned = false;
+            return PingVerdict::Allow;
+        }
+        self.violations += 1;
+        if self.close_after != 0 && self.violations >= self.close_after {
+            PingVerdict::Close
+        } else if self.policy == PingPolicy::Toast && !self.warned {
+            self.warned = true; // Once per series of violations
+            PingVerdict::Warn
+        } else {
+            PingVerdict::Drop
+        }
+    }
+}
+
+/// Which connection limit was reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitReason {
+    Ip,
+    Unauthenticated,
+    User,
+}
+
+impl LimitReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitReason::Ip => "ip",
+            LimitReason::Unauthenticated => "unauthenticated",
+            LimitReason::User => "user",
+        }
+    }
+}
+
+/// Counts open WebSocket connections
+#[derive(Debug, Default)]
+pub struct ConnectionTracker {
+    per_ip: DashMap<IpAddr, usize>,
+    unauthenticated: AtomicUsize,
+    per_user: DashMap<Uuid, usize>,
+}
+
+impl ConnectionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn count(&self) -> usize {
+        self.per_ip.iter().map(|count| *count).sum()
+    }
+    pub fn count_unauthenticated(&self) -> usize {
+        self.unauthenticated.load(Ordering::Relaxed)
+    }
+    /// Registers new unauthenticated connection
+    pub fn connect(self: &Arc<Self>, ip: IpAddr, config: &WebSocketConfig) -> Result<ConnectionGuard, LimitReason> {
+        if !increment(&self.per_ip, ip, config.max_per_ip) {
+            return Err(LimitReason::Ip);
+        }
+        let max = config.max_unauthenticated;
+        if self.unauthenticated.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| (max == 0 || count < max).then_some(count + 1)).is_err() {
+            decrement(&self.per_ip, ip);
+            return Err(LimitReason::Unauthenticated);
+        }
+        Ok(ConnectionGuard { tracker: Arc::clone(self), ip, authenticated: None })
+    }
+}
+
+/// Increments counter if it's below the limit, 0 means no limit
+fn increment<K: std::hash::Hash + Eq>(map: &DashMap<K, usize>, key: K, max: usize) -> bool {
+    let mut count = map.entry(key).or_insert(0);
+    if max != 0 && *count >= max {
+        return false;
+    }
+    *count += 1;
+    true
+}
+
+fn decrement<K: std::hash::Hash + Eq>(map: &DashMap<K, usize>, key: K) {
+    if let Some(mut count) = map.get_mut(&key) {
+        *count = count.saturating_sub(1);
+    }
+    map.remove_if(&key, |_, count| *count == 0);
+}
+
+/// Holds the connection slot until dropped
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    tracker: Arc<ConnectionTracker>,
+    ip: IpAddr,
+    authenticated: Option<Uuid>,
+}
+
+impl ConnectionGuard {
+    /// Moves the connection from unauthenticated to the user's connections
+    pub fn authenticate(&mut self, uuid: Uuid, max_per_user: usize) -> Result<(), LimitReason> {
+        if !increment(&self.tracker.per_user, uuid, max_per_user) {
+            return Err(LimitReason::User);
+        }
+        self.tracker.unauthenticated.fetch_sub(1, Ordering::AcqRel);
+        self.authenticated = Some(uuid);
+        Ok(())
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        match self.authenticated {
+            Some(uuid) => decrement(&self.tracker.per_user, uuid),
+            None => { self.tracker.unauthenticated.fetch_sub(1, Ordering::AcqRel); },
+        }
+        decrement(&self.tracker.per_ip, self.ip);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn connection_limits() {
+    let tracker = Arc::new(ConnectionTracker::new());
+    let config = WebSocketConfig { max_per_ip: 2, max_unauthenticated: 3, max_per_user: 1, ..Default::default() };
+    let ip: IpAddr = [127, 0, 0, 1].into();
+    let uuid = Uuid::nil();
+
+    let mut first = tracker.connect(ip, &config).unwrap();
+    let mut second = tracker.connect(ip, &config).unwrap();
+    assert_eq!(tracker.connect(ip, &config).unwrap_err(), LimitReason::Ip);
+    let _other = tracker.connect([127, 0, 0, 2].into(), &config).unwrap();
+    assert_eq!(tracker.connect([127, 0, 0, 3].into(), &config).unwrap_err(), LimitReason::Unauthenticated);
+
+    first.authenticate(uuid, config.max_per_user).unwrap();
+    assert_eq!(second.authenticate(uuid, config.max_per_user).unwrap_err(), LimitReason::User);
+    assert_eq!(tracker.count_unauthenticated(), 2);
+    drop(first);
+    second.authenticate(uuid, config.max_per_user).unwrap();
+    drop(second);
+    assert_eq!(tracker.count(), 1);
+    assert!(tracker.per_user.is_empty());
+
+    // Oversized pings don't drain the rate budget
+    let limits = RateLimits { ping_rate: 2, ping_size: 10, ping_close_after: 0, ..Default::default() };
+    let mut limiter = PingLimiter::new(&limits);
+    for _ in 0..5 {
+        assert_ne!(limiter.check(100), PingVerdict::Allow);
+    }
+    assert_eq!(limiter.check(5), PingVerdict::Allow);
+    assert_eq!(limiter.check(5), PingVerdict::Allow);
+    assert_ne!(limiter.check(0), PingVerdict::Allow, "rate budget is spent");
+}
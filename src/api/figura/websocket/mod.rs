@@ -0,0 +1,590 @@
+use std::{borrow::Cow, collections::HashSet, net::{IpAddr, SocketAddr}, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket},
+        ConnectInfo, State, WebSocketUpgrade,
+    },
+    http::HeaderMap,
+    response::Response,
+};
+use chrono::Utc;
+use tracing::{debug, error, info, trace, warn};
+use tokio::time::{Instant, MissedTickBehavior};
+use uuid::Uuid;
+
+use crate::{auth::{BanInfo, BanSource, Userinfo}, state::{labels, AbuseConfig, RateLimits, WebSocketConfig}, AppState};
+use super::{profile::send_event, types::{C2SMessage, S2CMessage}};
+use abuse::{AbuseAction, AbuseDetector, Detection};
+use hub::{Outbox, Outgoing};
+use limits::{ConnectionGuard, LimitReason, PingLimiter, PingVerdict};
+
+mod abuse;
+mod hub;
+mod limits;
+
+pub use abuse::AbuseTracker;
+pub use hub::Hub;
+pub use limits::ConnectionTracker;
+
+pub async fn handler(
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    let config = state.config.read().await.websocket.clone();
+    let ip = client_ip(config.real_ip_header.as_deref(), &headers, addr.ip());
+    let guard = state.connections.connect(ip, &config);
+    ws.on_upgrade(move |mut socket| async move {
+        match guard {
+            Ok(guard) => handle_socket(socket, state, guard).await,
+            Err(reason) => {
+                warn!("[WebSocket] Connection from {ip} exceeds {} limit! Sending close with Too Many Connections code.", reason.as_str());
+                too_many_connections(&state, reason);
+                let _ = socket.send(Message::Close(Some(CloseFrame { code: TOO_MANY_CONNECTIONS, reason: "Too many connections".into() }))).await;
+            },
+        }
+    })
+}
+
+/// IP from the header of the reverse proxy, if it's configured
+fn client_ip(header: Option<&str>, headers: &HeaderMap, addr: IpAddr) -> IpAddr {
+    header.and_then(|header| headers.get(header))
+        .and_then(|value| value.to_str().ok())
+        // X-Forwarded-For: spoofed, client - only the last one is added by our proxy
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|value| value.trim().parse::<IpAddr>().ok())
+        .unwrap_or(addr)
+}
+
+fn too_many_connections(state: &AppState, reason: LimitReason) {
+    state.metrics.inc(&format!("sculptor_ws_rejected_total{}", labels(&[("reason", reason.as_str())])));
+}
+
+// Figura close codes (see note.txt)
+const UNAUTHORIZED: u16 = 3000;
+const RE_AUTH: u16 = 4000;
+const BANNED: u16 = 4001;
+const TOO_MANY_CONNECTIONS: u16 = 4002;
+
+/// Reason to close the connection
+#[derive(Debug)]
+struct CloseReason {
+    code: u16,
+    reason: Cow<'static, str>,
+}
+
+impl CloseReason {
+    fn new(code: u16, reason: impl Into<Cow<'static, str>>) -> Self {
+        Self { code, reason: reason.into() }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct WSUser {
+    username: String,
+    uuid: Uuid,
+}
+
+/// Protocol state of the connection
+enum SessionState {
+    /// Only `C2SMessage::Token` is accepted
+    Unauthenticated,
+    Authenticated(AuthSession),
+}
+
+struct AuthSession {
+    user: WSUser,
+    /// Users whose pings are received
+    subscriptions: HashSet<Uuid>,
+}
+
+impl SessionState {
+    fn name(&self) -> String {
+        if let SessionState::Authenticated(session) = self {
+            format!(" ({})", session.user.username)
+        } else {
+            String::new()
+        }
+    }
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, guard: ConnectionGuard) {
+    debug!("[WebSocket] New unknown connection!");
+    let (limits, ws_config, abuse) = {
+        let config = state.config.read().await;
+        (config.rate_limits.clone(), config.websocket.clone(), config.abuse.clone())
+    };
+    let mut conn = Connection::new(state.clone(), guard, &limits, &ws_config, &abuse);
+    let auth_deadline = tokio::time::sleep(ws_config.auth_timeout());
+    tokio::pin!(auth_deadline);
+    // Keepalive, idle timeout is checked only with it
+    let keepalive = ws_config.ping_interval != 0;
+    let period = ws_config.ping_interval().max(Duration::from_secs(1));
+    let mut keepalive_tick = tokio::time::interval_at(Instant::now() + period, period);
+    keepalive_tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last_seen = Instant::now();
+
+    let close = loop {
+        tokio::select! {
+            // Main loop what receving messages from WebSocket
+            msg = socket.recv() => {
+                trace!("[WebSocket{}] Raw: {msg:?}", conn.name());
+                last_seen = Instant::now();
+                let data = match msg {
+                    Some(Ok(Message::Binary(data))) => data,
+                    Some(Ok(Message::Close(_))) => {
+                        info!("[WebSocket{}] Connection successfully closed!", conn.name());
+                        break None;
+                    },
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue, // Pings are answered by axum
+                    Some(Ok(Message::Text(_))) => break Some(CloseReason::new(close_code::UNSUPPORTED, "Text frames are not supported")),
+                    Some(Err(_)) | None => {
+                        debug!("[WebSocket{}] Receive error! Connection terminated!", conn.name());
+                        break None;
+                    },
+                };
+                if let Err(reason) = conn.handle_message(&mut socket, &data).await {
+                    break Some(reason);
+                }
+            },
+            // Frames from the hub
+            msg = conn.outbox.pop() => {
+                let msg = match msg {
+                    Outgoing::Frame(msg) => msg,
+                    Outgoing::Close(reason) => break Some(reason),
+                };
+                debug!("[WebSocketSubscribe{}] Answering: {}", conn.name(), hex::encode(&msg));
+                if socket.send(Message::Binary(msg.to_vec())).await.is_err() {
+                    warn!("[WebSocketSubscriber{}] Send error! Connection terminated!", conn.name());
+                    break None;
+                }
+            },
+            () = &mut auth_deadline, if matches!(conn.session, SessionState::Unauthenticated) => {
+                debug!("[WebSocket] Authentication timeout!");
+                break Some(CloseReason::new(UNAUTHORIZED, "Authentication timeout"));
+            },
+            _ = keepalive_tick.tick(), if keepalive => {
+                if ws_config.idle_timeout().is_some_and(|timeout| last_seen.elapsed() > timeout) {
+                    info!("[WebSocket{}] No response for {} seconds! Connection terminated!", conn.name(), last_seen.elapsed().as_secs());
+                    break Some(CloseReason::new(close_code::AWAY, "Idle timeout"));
+                }
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    debug!("[WebSocket{}] Keepalive error! Connection terminated!", conn.name());
+                    break None;
+                }
+            },
+        }
+    };
+    if let Some(close) = close {
+        debug!("[WebSocket{}] Closing with {close:?}", conn.name());
+        let _ = socket.send(Message::Close(Some(CloseFrame { code: close.code, reason: close.reason }))).await;
+    }
+
+    // Closing connection
+    if let SessionState::Authenticated(session) = conn.session {
+        debug!("[WebSocket ({})] Removing session data", session.user.username);
+        if conn.outbox.dropped() != 0 {
+            debug!("[WebSocket ({})] {} frames were dropped for the slow connection", session.user.username, conn.outbox.dropped());
+        }
+        state.hub.leave(session.user.uuid, &conn.outbox, &session.subscriptions);
+        state.user_manager.remove(&session.user.uuid);
+    } else {
+        debug!("[WebSocket] Nothing to remove");
+    }
+    state.hub.remove(&conn.outbox);
+}
+
+/// Disconnects the banned user right away, logs out and hides the avatar from subscribers
+pub async fn enforce_ban(state: &AppState, uuid: &Uuid) {
+    // Ban might be already expired
+    if !state.user_manager.is_banned(uuid) {
+        return;
+    }
+    let details = state.user_manager.ban_info(uuid).and_then(|ban| ban.details());
+    state.hub.send(uuid, S2CMessage::Toast(2, "You're banned!", details.as_deref()).to_vec());
+    let count = state.hub.close(uuid, BANNED, "You're banned!");
+    if count != 0 {
+        info!("[WebSocket] Closing {count} connections of banned {uuid}");
+    }
+    state.user_manager.remove(uuid);
+    send_event(state, uuid).await;
+}
+
+/// Tells the user whether the pings are muted
+pub fn notify_mute(state: &AppState, uuid: &Uuid) {
+    state.hub.send(uuid, mute_toast(state, uuid));
+}
+
+fn mute_toast(state: &AppState, uuid: &Uuid) -> Vec<u8> {
+    match state.user_manager.mute_info(uuid) {
+        Some(mute) => S2CMessage::Toast(1, "Your pings are muted", mute.details().as_deref()).to_vec(),
+        None => S2CMessage::Toast(0, "Your pings are unmuted", None).to_vec(),
+    }
+}
+
+/// Escalates strikes of abuse detection
+async fn punish(state: &AppState, outbox: &Outbox, user: &WSUser, detection: Detection) -> Result<(), CloseReason> {
+    let config = state.config.read().await.abuse.clone();
+    let action = state.abuse.strike(user.uuid, config.forgive_after());
+    warn!("[WebSocket ({})] Abuse detected by {} heuristic: {}! Punishment: {}", user.username, detection.heuristic, detection.evidence, action.as_str());
+    state.metrics.inc(&format!("sculptor_abuse_actions_total{}", labels(&[("action", action.as_str())])));
+    let info = |seconds| BanInfo {
+        reason: Some(format!("Automatic, {}: {}", detection.heuristic, detection.evidence)),
+        issuer: Some("Abuse detection".to_string()),
+        expires: BanInfo::expiry_after(Utc::now(), seconds),
+        ..Default::default()
+    };
+    match action {
+        AbuseAction::Warn => state.hub.send_to(outbox, S2CMessage::Toast(1, "Slow down!", Some("Further spam will be punished")).to_vec()),
+        AbuseAction::Mute => {
+            // Doesn't shorten the admin's mute
+            if !state.user_manager.get_by_uuid(&user.uuid).is_some_and(|user| user.is_muted())
+                && state.user_manager.mute(&user.uuid, info(config.mute_duration)).is_ok() {
+                notify_mute(state, &user.uuid);
+            }
+        },
+        AbuseAction::Disconnect => return Err(CloseReason::new(close_code::POLICY, "Abuse detected")),
+        AbuseAction::Ban => {
+            let bans = [(BanSource::Automatic, info(config.ban_duration))].into();
+            state.user_manager.ban(&Userinfo { uuid: user.uuid, username: user.username.clone(), banned: true, bans, ..Default::default() });
+            enforce_ban(state, &user.uuid).await;
+        },
+    }
+    Ok(())
+}
+
+/// Warns every connected user and closes all connections with Service Restart code
+pub async fn shutdown(state: &AppState) {
+    let config = state.config.read().await.shutdown.clone();
+    if !config.toast.is_empty() {
+        state.hub.send_all(S2CMessage::Toast(1, &config.toast, config.description.as_deref()).to_vec());
+    }
+    let count = state.hub.close_all(close_code::RESTART, "Server restarting");
+    info!("[WebSocket] Closing {count} connections");
+}
+
+/// Where direct answers to the client go, so messages can be handled without a real WebSocket in tests
+trait Reply {
+    /// Returns false if the connection is broken
+    async fn reply(&mut self, frame: Vec<u8>) -> bool;
+}
+
+impl Reply for WebSocket {
+    async fn reply(&mut self, frame: Vec<u8>) -> bool {
+        self.send(Message::Binary(frame)).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+impl Reply for Vec<Vec<u8>> {
+    async fn reply(&mut self, frame: Vec<u8>) -> bool {
+        self.push(frame);
+        true
+    }
+}
+
+/// Single WebSocket connection
+struct Connection {
+    state: AppState,
+    session: SessionState,
+    limiter: PingLimiter,
+    detector: AbuseDetector,
+    guard: ConnectionGuard,
+    /// Subscriptions limit, 0 means no limit
+    max_subscriptions: usize,
+    /// Frames from the hub to this connection
+    outbox: Arc<Outbox>,
+}
+
+impl Connection {
+    fn new(state: AppState, guard: ConnectionGuard, limits: &RateLimits, ws_config: &WebSocketConfig, abuse: &AbuseConfig) -> Self {
+        Self {
+            session: SessionState::Unauthenticated,
+            limiter: PingLimiter::new(limits),
+            detector: AbuseDetector::new(abuse),
+            guard,
+            max_subscriptions: ws_config.max_subscriptions,
+            outbox: state.hub.outbox(ws_config.queue_size),
+            state,
+        }
+    }
+    fn name(&self) -> String {
+        self.session.name()
+    }
+
+    async fn handle_message(&mut self, socket: &mut impl Reply, data: &[u8]) -> Result<(), CloseReason> {
+        let msg = match C2SMessage::try_from(data) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("[WebSocket{}] This message is not from Figura! {e}", self.name());
+                debug!("[WebSocket{}] Broken data: {}", self.name(), hex::encode(data));
+                return Err(CloseReason::new(close_code::PROTOCOL, "Malformed message"));
+            },
+        };
+        debug!("[WebSocket{}] MSG: {:?}, HEX: {}", self.name(), msg, hex::encode(msg.to_vec()));
+
+        let session = match &mut self.session {
+            SessionState::Unauthenticated => return match msg {
+                C2SMessage::Token(token) => {
+                    trace!("[WebSocket] C2S : Token");
+                    self.authenticate(socket, token).await
+                },
+                _ => {
+                    warn!("[WebSocket] Message before authentication! Sending close with Unauthorized code.");
+                    Err(CloseReason::new(UNAUTHORIZED, "Unauthorized"))
+                },
+            },
+            SessionState::Authenticated(session) => session,
+        };
+
+        let detection = match &msg {
+            C2SMessage::Ping(_, _, ping) => self.detector.ping(ping.len()),
+            C2SMessage::Sub(uuid) => self.detector.sub(*uuid),
+            C2SMessage::Unsub(uuid) => {
+                self.detector.unsub(*uuid);
+                None
+            },
+            C2SMessage::Token(_) => None,
+        };
+        if let Some(detection) = detection {
+            punish(&self.state, &self.outbox, &session.user, detection).await?;
+        }
+
+        // Checking ban list, also catches the automatic ban
+        if self.state.user_manager.is_banned(&session.user.uuid) {
+            warn!("[WebSocket] Detected banned user with active WebSocket! Sending close with Banned code.");
+            let details = self.state.user_manager.ban_info(&session.user.uuid).and_then(|ban| ban.details());
+            socket.reply(S2CMessage::Toast(2, "You're banned!", details.as_deref()).to_vec()).await;
+            return Err(CloseReason::new(BANNED, "You're banned!"));
+        }
+
+        let name = format!(" ({})", session.user.username);
+        match msg {
+            C2SMessage::Token(_) => {
+                warn!("[WebSocket{name}] Repeated authentication!");
+                return Err(CloseReason::new(close_code::PROTOCOL, "Already authenticated"));
+            },
+            C2SMessage::Ping(_, sync, ping) => {
+                trace!("[WebSocket{name}] C2S : Ping");
+                match self.limiter.check(ping.len()) {
+                    PingVerdict::Allow => (),
+                    PingVerdict::Drop => {
+                        debug!("[WebSocket{name}] Ping exceeds rate limits! Dropping");
+                        return Ok(());
+                    },
+                    PingVerdict::Warn => {
+                        debug!("[WebSocket{name}] Ping exceeds rate limits! Dropping and warning");
+                        socket.reply(S2CMessage::Toast(1, "Ping rate limit exceeded!", None).to_vec()).await;
+                        return Ok(());
+                    },
+                    PingVerdict::Close => {
+                        warn!("[WebSocket{name}] Too many ping limit violations! Sending close with Policy Violation code.");
+                        return Err(CloseReason::new(close_code::POLICY, "Ping rate limit exceeded"));
+                    },
+                }
+                let data = into_s2c_ping(data, session.user.uuid);
+                // Synced ping runs on the sender too, in the same order as for subscribers
+                if sync {
+                    self.state.hub.send_to(&self.outbox, data.clone());
+                }
+                if self.state.user_manager.get_by_uuid(&session.user.uuid).is_some_and(|user| user.is_muted()) {
+                    trace!("[WebSocket{name}] Muted, ping isn't relayed");
+                    return Ok(());
+                }
+                if self.state.user_manager.is_shadow_banned(&session.user.uuid) {
+                    trace!("[WebSocket{name}] Shadow banned, ping isn't relayed");
+                    return Ok(());
+                }
+                if self.state.hub.publish(&session.user.uuid, data) == Some(0) {
+                    debug!("[WebSocket{name}] Failed to send Ping! Maybe there's no one to send");
+                };
+            },
+            // Subscribing
+            C2SMessage::Sub(uuid) => {
+                trace!("[WebSocket{name}] C2S : Sub");
+                // Ignoring self and repeated Sub
+                if uuid == session.user.uuid || session.subscriptions.contains(&uuid) {
+                    return Ok(());
+                };
+                if self.max_subscriptions != 0 && session.subscriptions.len() >= self.max_subscriptions {
+                    warn!("[WebSocket{name}] Subscriptions limit reached! Ignoring Sub to {uuid}");
+                    return Ok(());
+                }
+                session.subscriptions.insert(uuid);
+                if !self.state.hub.is_online(&uuid) {
+                    debug!("[WebSocket{name}] Subscribed to {uuid} that is not connected yet");
+                }
+                self.state.hub.subscribe(uuid, &self.outbox);
+            },
+            // Unsubscribing
+            C2SMessage::Unsub(uuid) => {
+                trace!("[WebSocket{name}] C2S : Unsub");
+                if session.subscriptions.remove(&uuid) {
+                    self.state.hub.unsubscribe(uuid, &self.outbox);
+                } else {
+                    debug!("[WebSocket{name}] Unsub from {uuid} without subscription");
+                }
+            },
+        }
+        Ok(())
+    }
+
+    async fn authenticate(&mut self, socket: &mut impl Reply, token: &[u8]) -> Result<(), CloseReason> {
+        let token = std::str::from_utf8(token)
+            .map_err(|_| CloseReason::new(close_code::PROTOCOL, "Token is not UTF-8"))?
+            .to_string();
+        let user = match self.state.user_manager.get(&token) { // The principle is simple: if there is no token in authenticated, then it's "dirty hacker" :D
+            Some(t) => WSUser { username: t.username.clone(), uuid: t.uuid },
+            None => {
+                warn!("[WebSocket] Authentication error! Sending close with Re-auth code.");
+                debug!("[WebSocket] Tried to log in with {token}"); // Tried to log in with token: {token}
+                return Err(CloseReason::new(RE_AUTH, "Re-auth"));
+            },
+        };
+        if self.state.user_manager.is_banned(&user.uuid) {
+            warn!("[WebSocket ({})] Banned user tried to connect! Sending close with Banned code.", user.username);
+            return Err(CloseReason::new(BANNED, "You're banned!"));
+        }
+        let max_per_user = self.state.config.read().await.websocket.max_per_user;
+        if let Err(reason) = self.guard.authenticate(user.uuid, max_per_user) {
+            warn!("[WebSocket ({})] Too many connections! Sending close with Too Many Connections code.", user.username);
+            too_many_connections(&self.state, reason);
+            return Err(CloseReason::new(TOO_MANY_CONNECTIONS, "Too many connections"));
+        }
+        self.state.hub.join(user.uuid, &self.outbox);
+        self.session = SessionState::Authenticated(AuthSession { user, subscriptions: HashSet::new() });
+
+        debug!("[WebSocket{}] Answering: Auth", self.name());
+        if !socket.reply(S2CMessage::Auth.to_vec()).await {
+            warn!("[WebSocket{}] Send error! Connection terminated!", self.name());
+        }
+        if let SessionState::Authenticated(session) = &self.session {
+            if self.state.user_manager.get_by_uuid(&session.user.uuid).is_some_and(|user| user.is_muted()) {
+                self.state.hub.send_to(&self.outbox, mute_toast(&self.state, &session.user.uuid));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn into_s2c_ping(buf: &[u8], uuid: Uuid) -> Vec<u8> {
+    use std::iter::once;
+    once(1)
+        .chain(uuid.into_bytes().iter().copied())
+        .chain(buf[1..].iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn s2c_ping_encoding() {
+    let uuid = Uuid::from_u128(0x66004548_4de5_49de_bade_9c3933d8eb97);
+    for sync in [false, true] {
+        let c2s = C2SMessage::Ping(42, sync, b"ping").to_vec();
+        assert_eq!(into_s2c_ping(&c2s, uuid), S2CMessage::Ping(uuid, 42, sync, b"ping").to_vec());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn client_ip_from_proxy() {
+    let addr = IpAddr::from([10, 0, 0, 1]);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2".parse().unwrap());
+    assert_eq!(client_ip(Some("X-Forwarded-For"), &headers, addr), IpAddr::from([2, 2, 2, 2]), "the first one is sent by the client");
+    assert_eq!(client_ip(None, &headers, addr), addr);
+    headers.insert("x-real-ip", "garbage".parse().unwrap());
+    assert_eq!(client_ip(Some("X-Real-IP"), &headers, addr), addr);
+}
+
+/// Connection of the logged in user, past the Token message
+#[cfg(test)]
+async fn connect(state: &AppState, uuid: Uuid) -> (Connection, Vec<Vec<u8>>) {
+    let config = state.config.read().await.clone();
+    let guard = state.connections.connect(IpAddr::from([127, 0, 0, 1]), &config.websocket).unwrap();
+    let mut conn = Connection::new(state.clone(), guard, &config.rate_limits, &config.websocket, &config.abuse);
+    let token = format!("token-{uuid}");
+    state.user_manager.insert(uuid, token.clone(), Userinfo { uuid, username: format!("user-{uuid}"), token: Some(token.clone()), ..Default::default() }).unwrap();
+    let mut replies = Vec::new();
+    conn.handle_message(&mut replies, &C2SMessage::Token(token.as_bytes()).to_vec()).await.unwrap();
+    assert_eq!(replies, [S2CMessage::Auth.to_vec()]);
+    (conn, Vec::new())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn shadow_banned_pings() {
+    let state = AppState::test();
+    let alice = Uuid::from_u128(1);
+    let (mut conn, mut replies) = connect(&state, alice).await;
+    let subscriber = state.hub.outbox(16);
+    state.hub.subscribe(alice, &subscriber);
+    let (c2s, s2c) = (C2SMessage::Ping(7, true, b"hi").to_vec(), S2CMessage::Ping(alice, 7, true, b"hi").to_vec());
+
+    state.user_manager.set_shadow_ban(&alice, true).unwrap();
+    conn.handle_message(&mut replies, &c2s).await.unwrap();
+    assert_eq!(conn.outbox.drain(), std::slice::from_ref(&s2c), "synced ping still runs on the sender");
+    assert!(subscriber.drain().is_empty());
+
+    state.user_manager.set_shadow_ban(&alice, false).unwrap();
+    conn.handle_message(&mut replies, &c2s).await.unwrap();
+    assert_eq!(subscriber.drain(), [s2c]);
+    assert!(replies.is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn muted_pings() {
+    let state = AppState::test();
+    let alice = Uuid::from_u128(1);
+    state.user_manager.insert_user(alice, Userinfo { uuid: alice, ..Default::default() });
+    let mute = BanInfo { reason: Some("spam".to_string()), ..Default::default() };
+    state.user_manager.mute(&alice, mute.clone()).unwrap();
+
+    let (mut conn, mut replies) = connect(&state, alice).await;
+    assert_eq!(conn.outbox.drain(), [S2CMessage::Toast(1, "Your pings are muted", mute.details().as_deref()).to_vec()], "told on authentication");
+    let subscriber = state.hub.outbox(16);
+    state.hub.subscribe(alice, &subscriber);
+    let (c2s, s2c) = (C2SMessage::Ping(7, true, b"hi").to_vec(), S2CMessage::Ping(alice, 7, true, b"hi").to_vec());
+
+    conn.handle_message(&mut replies, &c2s).await.unwrap();
+    assert_eq!(conn.outbox.drain(), std::slice::from_ref(&s2c), "synced ping still runs on the sender");
+    assert!(subscriber.drain().is_empty());
+
+    state.user_manager.unmute(&alice).unwrap();
+    notify_mute(&state, &alice);
+    assert_eq!(conn.outbox.drain(), [S2CMessage::Toast(0, "Your pings are unmuted", None).to_vec()]);
+    conn.handle_message(&mut replies, &c2s).await.unwrap();
+    assert_eq!(subscriber.drain(), [s2c]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn ban_enforcement() {
+    let state = AppState::test();
+    let alice = Uuid::from_u128(1);
+    state.user_manager.insert(alice, "token".to_string(), Userinfo { uuid: alice, token: Some("token".to_string()), ..Default::default() }).unwrap();
+    let sessions = [state.hub.outbox(16), state.hub.outbox(16)];
+    for session in &sessions {
+        state.hub.join(alice, session);
+    }
+    let subscriber = state.hub.outbox(16);
+    state.hub.subscribe(alice, &subscriber);
+
+    enforce_ban(&state, &alice).await;
+    assert!(sessions.iter().all(|session| session.drain().is_empty()), "not banned");
+    assert!(state.user_manager.is_authenticated(&"token".to_string()));
+
+    let ban = BanInfo { reason: Some("griefing".to_string()), ..Default::default() };
+    state.user_manager.ban(&Userinfo { uuid: alice, banned: true, bans: [(BanSource::Api, ban.clone())].into(), ..Default::default() });
+    enforce_ban(&state, &alice).await;
+    let toast = S2CMessage::Toast(2, "You're banned!", ban.details().as_deref()).to_vec();
+    for session in &sessions {
+        assert!(matches!(session.pop().await, Outgoing::Frame(frame) if *frame == *toast), "toast goes before close");
+        assert!(matches!(session.pop().await, Outgoing::Close(CloseReason { code: BANNED, .. })));
+    }
+    assert!(!state.user_manager.is_authenticated(&"token".to_string()), "logged out");
+    assert_eq!(subscriber.drain(), [S2CMessage::Event(alice).to_vec()], "avatar is hidden from subscribers");
+}
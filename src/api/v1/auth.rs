@@ -0,0 +1,13 @@
+use axum::extract::State;
+
+use crate::{api::errors::internal_and_log, auth::Token, ApiResult, AppState};
+
+pub(super) async fn providers(
+    Token(token): Token,
+    State(state): State<AppState>,
+) -> ApiResult<String> {
+    let config = state.config.read().await.clone();
+    config.verify_token(&token)?;
+
+    serde_json::to_string_pretty(&state.auth_health.status(&config.auth_providers.0)).map_err(internal_and_log)
+}
@@ -3,7 +3,7 @@ use tokio::{fs, io::{self, BufWriter}};
 use tracing::warn;
 use uuid::Uuid;
 
-use crate::{api::figura::profile::send_event, auth::Token, ApiResult, AppState};
+use crate::{api::figura::{profile::{pending_avatar_file, send_event}, validate_avatar}, auth::Token, utils::calculate_sha256, ApiError, ApiResult, AppState};
 
 pub async fn upload_avatar(
     Path(uuid): Path<Uuid>,
@@ -19,10 +19,17 @@ pub async fn upload_avatar(
         "trying to upload the avatar for {}",
         uuid,
     );
+    if state.blocklist.is_blocked(&calculate_sha256(&request_data)) {
+        warn!("avatar is blocked");
+        return Err(ApiError::Forbidden);
+    }
+    validate_avatar(&state, uuid, &request_data).await?;
 
     let avatar_file = format!("avatars/{}.moon", &uuid);
     let mut file = BufWriter::new(fs::File::create(&avatar_file).await.unwrap());
     io::copy(&mut request_data.as_ref(), &mut file).await.unwrap();
+    // Replaces the upload waiting for approval too
+    let _ = fs::remove_file(pending_avatar_file(&uuid)).await;
     send_event(&state, &uuid).await;
 
     Ok("ok")
@@ -45,7 +52,7 @@ pub async fn delete_avatar(
         Ok(_) => {},
         Err(_) => {
             warn!("avatar doesn't exist");
-            return Err(crate::ApiError::NotFound)
+            return Err(ApiError::NotFound)
         }
     };
     send_event(&state, &uuid).await;
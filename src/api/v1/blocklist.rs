@@ -0,0 +1,110 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{extract::{Path, State}, Json};
+use chrono::Utc;
+use serde::Serialize;
+use tokio::fs;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::{errors::internal_and_log, figura::profile::send_event},
+    auth::Token,
+    state::{normalize_hash, BlockedAvatar},
+    utils::calculate_sha256,
+    ApiError, ApiResult, AppState,
+};
+
+use super::types::BlockRequest;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlocklistEntry {
+    #[serde(flatten)]
+    avatar: BlockedAvatar,
+    /// Users who have this avatar right now
+    users: Vec<Uuid>,
+}
+
+pub(super) async fn list(
+    Token(token): Token,
+    State(state): State<AppState>,
+) -> ApiResult<String> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    let mut owners = owners().await?;
+    let list: BTreeMap<_, _> = state.blocklist.list().into_iter().map(|(hash, avatar)| {
+        let users = owners.remove(&hash).unwrap_or_default();
+        (hash, BlocklistEntry { avatar, users })
+    }).collect();
+    serde_json::to_string_pretty(&list).map_err(internal_and_log)
+}
+
+pub(super) async fn block(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Json(json): Json<BlockRequest>,
+) -> ApiResult<String> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    let hash = normalize_hash(&json.hash).ok_or(ApiError::BadRequest)?;
+    info!("Trying block avatar: {hash}");
+    let avatar = BlockedAvatar {
+        reason: json.reason,
+        issuer: Some(json.issuer.unwrap_or_else(|| "API".to_string())),
+        created: Utc::now(),
+    };
+    state.blocklist.block(hash.clone(), avatar.clone()).await.map_err(internal_and_log)?;
+
+    // Hiding the avatar from subscribers
+    let users = owners().await?.remove(&hash).unwrap_or_default();
+    for uuid in &users {
+        send_event(&state, uuid).await;
+    }
+    serde_json::to_string_pretty(&BlocklistEntry { avatar, users }).map_err(internal_and_log)
+}
+
+pub(super) async fn unblock(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> ApiResult<&'static str> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    let hash = normalize_hash(&hash).ok_or(ApiError::BadRequest)?;
+    info!("Trying unblock avatar: {hash}");
+    if !state.blocklist.unblock(&hash).await.map_err(internal_and_log)? {
+        return Err(ApiError::NotFound);
+    }
+
+    for uuid in owners().await?.remove(&hash).unwrap_or_default() {
+        send_event(&state, &uuid).await;
+    }
+    Ok("ok")
+}
+
+/// Users by the hash of their stored avatar, approved or pending
+async fn owners() -> ApiResult<HashMap<String, Vec<Uuid>>> {
+    let mut owners: HashMap<String, Vec<Uuid>> = HashMap::new();
+    for dir in ["avatars", "avatars/pending"] {
+        let mut dir = match fs::read_dir(dir).await {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue, // Nothing was uploaded yet
+            Err(err) => return Err(internal_and_log(err)),
+        };
+        while let Some(entry) = dir.next_entry().await.map_err(internal_and_log)? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "moon") {
+                continue;
+            }
+            let Some(uuid) = path.file_stem().and_then(|name| Uuid::try_parse(&name.to_string_lossy()).ok()) else { continue };
+            let avatar = fs::read(&path).await.map_err(internal_and_log)?;
+            owners.entry(calculate_sha256(&avatar)).or_default().push(uuid);
+        }
+    }
+    for users in owners.values_mut() {
+        users.sort();
+        users.dedup(); // Both approved and pending avatars of the same user
+    }
+    Ok(owners)
+}
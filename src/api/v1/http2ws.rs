@@ -1,7 +1,7 @@
 use axum::extract::{Query, State};
 use tracing::{debug, trace, warn};
 
-use crate::{api::errors::{error_and_log, internal_and_log}, auth::Token, ApiResult, AppState};
+use crate::{api::errors::error_and_log, auth::Token, ApiResult, AppState};
 use super::types::UserUuid;
 
 pub(super) async fn verify(
@@ -27,14 +27,17 @@ pub(super) async fn raw(
     match query.uuid {
         Some(uuid) => {
             // for only one
-            let tx = state.session.get(&uuid).ok_or_else(|| { warn!("unknown uuid"); crate::ApiError::NotFound })?;
-            tx.value().send(payload).await.map_err(internal_and_log)?;
+            if state.hub.send(&uuid, payload) == 0 {
+                warn!("unknown uuid");
+                return Err(crate::ApiError::NotFound);
+            }
             Ok("ok")
         },
         None => {
             // for all
-            warn!("uuid doesnt defined");
-            Err(crate::ApiError::NotFound)
+            let count = state.hub.send_all(payload);
+            debug!("sent to {count} connections");
+            Ok("ok")
         },
     }
 }
@@ -53,8 +56,10 @@ pub(super) async fn sub_raw(
     match query.uuid {
         Some(uuid) => {
             // for only one
-            let tx = state.broadcasts.get(&uuid).ok_or_else(|| { warn!("unknown uuid"); crate::ApiError::NotFound })?;
-            tx.value().send(payload).map_err(internal_and_log)?;
+            if state.hub.publish(&uuid, payload).is_none() {
+                warn!("unknown uuid");
+                return Err(crate::ApiError::NotFound);
+            }
             Ok("ok")
         },
         None => {
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use axum::{extract::State, Json};
+use tracing::info;
+
+use crate::{api::{errors::internal_and_log, figura::S2CMessage}, auth::Token, ApiError, ApiResult, AppState};
+use super::types::{ChatMessage, Delivered, NoticeMessage, Recipients, ToastMessage};
+
+pub(super) async fn toast(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Json(json): Json<ToastMessage>
+) -> ApiResult<String> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    // Title and description are separated by NUL
+    if json.kind > 3 || json.title.contains('\0') {
+        return Err(ApiError::BadRequest);
+    }
+    info!("Sending toast: {}", json.title);
+
+    let msg = S2CMessage::Toast(json.kind, &json.title, json.description.as_deref());
+    deliver(&state, &json.to, msg)
+}
+
+pub(super) async fn chat(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Json(json): Json<ChatMessage>
+) -> ApiResult<String> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    info!("Sending chat message: {}", json.text);
+
+    deliver(&state, &json.to, S2CMessage::Chat(&json.text))
+}
+
+pub(super) async fn notice(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Json(json): Json<NoticeMessage>
+) -> ApiResult<String> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    info!("Sending notice: {}", json.kind);
+
+    deliver(&state, &json.to, S2CMessage::Notice(json.kind))
+}
+
+fn deliver(state: &AppState, to: &Recipients, msg: S2CMessage) -> ApiResult<String> {
+    let delivered = match to {
+        Recipients::All => state.hub.send_all(msg.to_vec()),
+        Recipients::One(uuid) => state.hub.send(uuid, msg.to_vec()),
+        Recipients::Many(uuids) => uuids.iter().collect::<HashSet<_>>() // Without duplicates
+            .into_iter().map(|uuid| state.hub.send(uuid, msg.to_vec())).sum(),
+    };
+    serde_json::to_string_pretty(&Delivered { delivered }).map_err(internal_and_log)
+}
@@ -0,0 +1,33 @@
+use axum::extract::State;
+
+use crate::{auth::{CircuitState, Token}, state::labels, ApiResult, AppState};
+
+pub(super) async fn metrics(
+    Token(token): Token,
+    State(state): State<AppState>,
+) -> ApiResult<String> {
+    let config = state.config.read().await.clone();
+    config.verify_token(&token)?;
+
+    let mut gauges = vec![
+        ("sculptor_uptime_seconds".to_string(), state.uptime.elapsed().as_secs_f64()),
+        ("sculptor_authenticated_users".to_string(), state.user_manager.count_authenticated() as f64),
+        ("sculptor_ws_sessions".to_string(), state.hub.sessions() as f64),
+        ("sculptor_ws_channels".to_string(), state.hub.channels() as f64),
+        ("sculptor_ws_dropped_frames_total".to_string(), state.hub.dropped() as f64),
+        ("sculptor_ws_connections".to_string(), state.connections.count() as f64),
+        ("sculptor_ws_unauthenticated".to_string(), state.connections.count_unauthenticated() as f64),
+    ];
+    for provider in state.auth_health.status(&config.auth_providers.0) {
+        let label = labels(&[("provider", &provider.name)]);
+        gauges.extend([
+            (format!("sculptor_auth_provider_successes_total{label}"), provider.successes as f64),
+            (format!("sculptor_auth_provider_failures_total{label}"), provider.failures as f64),
+            (format!("sculptor_auth_provider_consecutive_failures{label}"), provider.consecutive_failures as f64),
+            (format!("sculptor_auth_provider_latency_ms{label}"), provider.avg_latency_ms as f64),
+            (format!("sculptor_auth_provider_circuit_open{label}"), (provider.circuit == CircuitState::Open) as u8 as f64),
+        ]);
+    }
+
+    Ok(state.metrics.render(&gauges))
+}
@@ -5,17 +5,38 @@ mod http2ws;
 mod users;
 mod types;
 mod avatars;
+mod blocklist;
+mod auth;
+mod metrics;
+mod message;
+mod moderation;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/verify", get(http2ws::verify))
         .route("/raw", post(http2ws::raw))
         .route("/sub/raw", post(http2ws::sub_raw))
+        .route("/message/toast", post(message::toast))
+        .route("/message/chat", post(message::chat))
+        .route("/message/notice", post(message::notice))
         .route("/user/list", get(users::list))
         .route("/user/sessions", get(users::list_sessions))
         .route("/user/create", post(users::create_user))
         .route("/user/:uuid/ban", post(users::ban))
         .route("/user/:uuid/unban", post(users::unban))
+        .route("/user/:uuid/shadowban", post(users::shadow_ban))
+        .route("/user/:uuid/mute", post(users::mute))
+        .route("/user/:uuid/unmute", post(users::unmute))
+        .route("/user/:uuid/unshadowban", post(users::shadow_unban))
+        .route("/user/:uuid/rebind", post(users::rebind))
         .route("/avatar/:uuid", put(avatars::upload_avatar).layer(DefaultBodyLimit::disable()))
         .route("/avatar/:uuid", delete(avatars::delete_avatar))
+        .route("/blocklist", get(blocklist::list).post(blocklist::block))
+        .route("/blocklist/:hash", delete(blocklist::unblock))
+        .route("/moderation", get(moderation::list))
+        .route("/moderation/:uuid", get(moderation::preview))
+        .route("/moderation/:uuid/approve", post(moderation::approve))
+        .route("/moderation/:uuid/reject", post(moderation::reject))
+        .route("/auth/providers", get(auth::providers))
+        .route("/metrics", get(metrics::metrics))
 }
\ No newline at end of file
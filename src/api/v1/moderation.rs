@@ -0,0 +1,163 @@
+use axum::{extract::{Path, State}, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::fs;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::{errors::internal_and_log, figura::{profile::{pending_avatar_file, send_event}, S2CMessage}},
+    auth::Token,
+    state::normalize_hash,
+    utils::{calculate_sha256, format_uuid},
+    ApiError, ApiResult, AppState,
+};
+
+use super::types::{ApproveRequest, RejectRequest};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PendingAvatar {
+    uuid: Uuid,
+    username: Option<String>,
+    hash: String,
+    size: usize,
+    uploaded: Option<DateTime<Utc>>,
+}
+
+pub(super) async fn list(
+    Token(token): Token,
+    State(state): State<AppState>,
+) -> ApiResult<String> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    let mut pending = Vec::new();
+    let mut dir = match fs::read_dir("avatars/pending").await {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok("[]".to_string()), // Nothing was uploaded yet
+        Err(err) => return Err(internal_and_log(err)),
+    };
+    while let Some(entry) = dir.next_entry().await.map_err(internal_and_log)? {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "moon") {
+            continue;
+        }
+        let Some(uuid) = path.file_stem().and_then(|name| Uuid::try_parse(&name.to_string_lossy()).ok()) else { continue };
+        let avatar = fs::read(&path).await.map_err(internal_and_log)?;
+        let uploaded = entry.metadata().await.and_then(|meta| meta.modified()).ok().map(DateTime::<Utc>::from);
+        pending.push(PendingAvatar {
+            uuid,
+            username: state.user_manager.get_by_uuid(&uuid).map(|user| user.username.clone()),
+            hash: calculate_sha256(&avatar),
+            size: avatar.len(),
+            uploaded,
+        });
+    }
+    pending.sort_by_key(|avatar| avatar.uploaded);
+    serde_json::to_string_pretty(&pending).map_err(internal_and_log)
+}
+
+pub(super) async fn preview(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+) -> ApiResult<Vec<u8>> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    fs::read(pending_avatar_file(&uuid)).await.map_err(not_found)
+}
+
+pub(super) async fn approve(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    Json(json): Json<ApproveRequest>,
+) -> ApiResult<&'static str> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    let hash = normalize_hash(&json.hash).ok_or(ApiError::BadRequest)?;
+    info!("Trying approve avatar of {uuid}: {hash}");
+    let pending = pending_avatar_file(&uuid);
+    let avatar = fs::read(&pending).await.map_err(not_found)?;
+    // Owner could upload another one after the preview
+    if calculate_sha256(&avatar) != hash {
+        warn!("Pending avatar of {uuid} was changed after the review");
+        return Err(ApiError::Conflict);
+    }
+    if state.blocklist.is_blocked(&hash) {
+        warn!("Pending avatar of {uuid} is blocked");
+        return Err(ApiError::Forbidden);
+    }
+    // Publishing the reviewed data, not whatever is pending at the moment
+    fs::write(format!("avatars/{}.moon", format_uuid(&uuid)), &avatar).await.map_err(internal_and_log)?;
+    if fs::read(&pending).await.is_ok_and(|avatar| calculate_sha256(&avatar) == hash) {
+        fs::remove_file(&pending).await.map_err(internal_and_log)?;
+    }
+    state.hub.send(&uuid, S2CMessage::Toast(0, "Your avatar was approved", None).to_vec());
+    send_event(&state, &uuid).await;
+    Ok("ok")
+}
+
+pub(super) async fn reject(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    json: Option<Json<RejectRequest>>,
+) -> ApiResult<&'static str> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    info!("Trying reject avatar of {uuid}");
+    fs::remove_file(pending_avatar_file(&uuid)).await.map_err(not_found)?;
+    let reason = json.and_then(|Json(json)| json.reason);
+    state.hub.send(&uuid, S2CMessage::Toast(1, "Your avatar was rejected", reason.as_deref()).to_vec());
+    send_event(&state, &uuid).await; // Owner gets the approved avatar back
+    Ok("ok")
+}
+
+fn not_found(err: std::io::Error) -> ApiError {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        ApiError::NotFound
+    } else {
+        internal_and_log(err)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn moderation_flow() {
+    use crate::api::figura::S2CMessage;
+
+    let state = AppState::test();
+    state.config.write().await.token = Some("admin".to_string());
+    let owner = Uuid::from_u128(rand::random());
+    let (conn, subscriber) = (state.hub.outbox(16), state.hub.outbox(16));
+    state.hub.join(owner, &conn);
+    state.hub.subscribe(owner, &subscriber);
+    let event = S2CMessage::Event(owner).to_vec();
+    let approve = |hash: String| approve(Token("admin".to_string()), State(state.clone()), Path(owner), Json(ApproveRequest { hash }));
+    let approved = format!("avatars/{}.moon", format_uuid(&owner));
+
+    fs::create_dir_all("avatars/pending").await.unwrap();
+    fs::write(pending_avatar_file(&owner), b"reviewed").await.unwrap();
+    let reviewed = calculate_sha256(b"reviewed");
+    // Uploaded again after the review
+    fs::write(pending_avatar_file(&owner), b"sneaky").await.unwrap();
+    assert!(matches!(approve(reviewed.clone()).await, Err(ApiError::Conflict)));
+    assert!(matches!(approve("nope".to_string()).await, Err(ApiError::BadRequest)));
+    assert!(conn.drain().is_empty() && subscriber.drain().is_empty());
+
+    fs::write(pending_avatar_file(&owner), b"reviewed").await.unwrap();
+    approve(reviewed.clone()).await.unwrap();
+    assert_eq!(fs::read(&approved).await.unwrap(), b"reviewed");
+    assert!(fs::metadata(pending_avatar_file(&owner)).await.is_err());
+    assert!(conn.drain().contains(&event), "owner reloads the avatar");
+    assert_eq!(subscriber.drain(), std::slice::from_ref(&event));
+    assert!(matches!(approve(reviewed).await, Err(ApiError::NotFound)));
+
+    fs::write(pending_avatar_file(&owner), b"rejected").await.unwrap();
+    reject(Token("admin".to_string()), State(state.clone()), Path(owner), None).await.unwrap();
+    assert!(fs::metadata(pending_avatar_file(&owner)).await.is_err());
+    assert!(conn.drain().contains(&event));
+    assert_eq!(subscriber.drain(), [event]);
+    fs::remove_file(approved).await.unwrap();
+}
@@ -1,7 +1,130 @@
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::{auth::{BanInfo, BanSource}, ApiError, ApiResult};
+
 #[derive(Deserialize)]
 pub(super) struct UserUuid {
     pub uuid: Option<Uuid>,
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub(super) struct ProviderName {
+    pub provider: Option<String>,
+}
+
+/// Who receives the message, every connected session if omitted
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+pub(super) enum Recipients {
+    #[default]
+    All,
+    One(Uuid),
+    Many(Vec<Uuid>),
+}
+
+#[derive(Deserialize)]
+pub(super) struct ToastMessage {
+    #[serde(default)]
+    pub to: Recipients,
+    /// 0 - default, 1 - warning, 2 - error, 3 - cookie
+    #[serde(default, rename = "type")]
+    pub kind: u8,
+    pub title: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct ChatMessage {
+    #[serde(default)]
+    pub to: Recipients,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub(super) struct NoticeMessage {
+    #[serde(default)]
+    pub to: Recipients,
+    #[serde(rename = "type")]
+    pub kind: u8,
+}
+
+#[derive(Serialize)]
+pub(super) struct Delivered {
+    /// Number of WebSocket connections that received the message
+    pub delivered: usize,
+}
+
+#[derive(Deserialize)]
+pub(super) struct BanSourceQuery {
+    pub source: Option<BanSource>,
+}
+
+/// Ban or mute
+#[derive(Deserialize, Default)]
+pub(super) struct BanRequest {
+    pub reason: Option<String>,
+    pub issuer: Option<String>,
+    /// Length in seconds
+    pub duration: Option<u64>,
+    /// Used if there's no duration, forever if both are omitted
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl BanRequest {
+    pub fn into_info(self) -> ApiResult<BanInfo> {
+        let created = Utc::now();
+        let expires = match self.duration {
+            Some(duration) => Some(BanInfo::expiry_after(created, duration).ok_or(ApiError::BadRequest)?),
+            None => self.expires,
+        };
+        // Such ban would never take effect
+        if expires.is_some_and(|expires| expires <= created) {
+            return Err(ApiError::BadRequest);
+        }
+        Ok(BanInfo {
+            reason: self.reason,
+            issuer: Some(self.issuer.unwrap_or_else(|| "API".to_string())),
+            created,
+            expires,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub(super) struct BlockRequest {
+    /// Hash from `equipped` of the profile
+    pub hash: String,
+    pub reason: Option<String>,
+    pub issuer: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct ApproveRequest {
+    /// Hash of the reviewed avatar from the pending list
+    pub hash: String,
+}
+
+#[derive(Deserialize)]
+pub(super) struct RejectRequest {
+    /// Shown to the owner
+    pub reason: Option<String>,
+}
+
+#[cfg(test)]
+#[test]
+fn ban_request_expiry() {
+    use chrono::TimeDelta;
+
+    let request = |duration, expires| BanRequest { duration, expires, ..Default::default() };
+    assert!(matches!(request(None, Some(Utc::now() - TimeDelta::hours(1))).into_info(), Err(ApiError::BadRequest)));
+    assert!(matches!(request(Some(0), None).into_info(), Err(ApiError::BadRequest)));
+    assert!(matches!(request(Some(u64::MAX), None).into_info(), Err(ApiError::BadRequest)));
+    let ban = request(None, Some(Utc::now() + TimeDelta::hours(1))).into_info().unwrap();
+    assert!(!ban.is_expired());
+    assert_eq!(ban.issuer.as_deref(), Some("API"));
+    let timed = request(Some(60), None).into_info().unwrap();
+    assert_eq!(timed.expires, Some(timed.created + TimeDelta::seconds(60)));
+    assert_eq!(request(None, None).into_info().unwrap().expires, None);
+}
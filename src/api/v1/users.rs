@@ -1,11 +1,12 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     Json
 };
 use tracing::{debug, info};
 use uuid::Uuid;
 
-use crate::{api::errors::internal_and_log, auth::{Token, Userinfo}, ApiResult, AppState};
+use crate::{api::{errors::internal_and_log, figura::{enforce_ban, notify_mute, profile::send_event}}, auth::{BanInfo, BanSource, Token, Userinfo}, ApiError, ApiResult, AppState};
+use super::types::{BanRequest, BanSourceQuery, ProviderName};
 
 pub(super) async fn create_user(
     Token(token): Token,
@@ -16,6 +17,10 @@ pub(super) async fn create_user(
 
     debug!("Creating new user: {json:?}");
     
+    let mut json = json;
+    if json.banned && json.bans.is_empty() {
+        json.bans.insert(BanSource::Api, BanInfo { issuer: Some("API".to_string()), ..Default::default() });
+    }
     state.user_manager.insert_user(json.uuid, json);
     Ok("ok")
 }
@@ -23,26 +28,118 @@ pub(super) async fn create_user(
 pub(super) async fn ban(
     Token(token): Token,
     State(state): State<AppState>,
-    Path(uuid): Path<Uuid>
+    Path(uuid): Path<Uuid>,
+    json: Option<Json<BanRequest>>
 ) -> ApiResult<&'static str> {
     state.config.read().await.clone().verify_token(&token)?;
 
-    info!("Trying ban user: {uuid}");
+    let Json(json) = json.unwrap_or_default();
+    let ban = json.into_info()?;
+    info!("Trying ban user: {uuid} ({ban:?})");
     
-    state.user_manager.ban(&Userinfo { uuid, banned: true, ..Default::default() });
+    state.user_manager.ban(&Userinfo { uuid, banned: true, bans: [(BanSource::Api, ban)].into(), ..Default::default() });
+    enforce_ban(&state, &uuid).await;
     Ok("ok")
 }
 
 pub(super) async fn unban(
     Token(token): Token,
     State(state): State<AppState>,
-    Path(uuid): Path<Uuid>
+    Path(uuid): Path<Uuid>,
+    Query(query): Query<BanSourceQuery>
 ) -> ApiResult<&'static str> {
     state.config.read().await.clone().verify_token(&token)?;
 
-    info!("Trying unban user: {uuid}");
+    let source = query.source.unwrap_or(BanSource::Api);
+    info!("Trying unban user: {uuid} ({source:?})");
     
-    state.user_manager.unban(&uuid);
+    if state.user_manager.unban(&uuid, source) {
+        info!("{uuid} is still banned by another source");
+    } else {
+        send_event(&state, &uuid).await; // Avatar is visible again
+    }
+    Ok("ok")
+}
+
+pub(super) async fn shadow_ban(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>
+) -> ApiResult<&'static str> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    info!("Trying shadow ban user: {uuid}");
+
+    state.user_manager.set_shadow_ban(&uuid, true).map_err(|_| ApiError::NotFound)?;
+    send_event(&state, &uuid).await; // Avatar disappears for subscribers
+    Ok("ok")
+}
+
+pub(super) async fn shadow_unban(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>
+) -> ApiResult<&'static str> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    info!("Trying shadow unban user: {uuid}");
+
+    state.user_manager.set_shadow_ban(&uuid, false).map_err(|_| ApiError::NotFound)?;
+    send_event(&state, &uuid).await;
+    Ok("ok")
+}
+
+pub(super) async fn mute(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    json: Option<Json<BanRequest>>
+) -> ApiResult<&'static str> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    let Json(json) = json.unwrap_or_default();
+    let mute = json.into_info()?;
+    info!("Trying mute user: {uuid} ({mute:?})");
+
+    state.user_manager.mute(&uuid, mute).map_err(|_| ApiError::NotFound)?;
+    notify_mute(&state, &uuid);
+    Ok("ok")
+}
+
+pub(super) async fn unmute(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>
+) -> ApiResult<&'static str> {
+    state.config.read().await.clone().verify_token(&token)?;
+
+    info!("Trying unmute user: {uuid}");
+
+    state.user_manager.unmute(&uuid).map_err(|_| ApiError::NotFound)?;
+    notify_mute(&state, &uuid);
+    Ok("ok")
+}
+
+pub(super) async fn rebind(
+    Token(token): Token,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    Query(query): Query<ProviderName>
+) -> ApiResult<&'static str> {
+    let config = state.config.read().await.clone();
+    config.verify_token(&token)?;
+
+    let provider = match query.provider {
+        Some(name) => Some(config.auth_providers.0.into_iter()
+            .find(|provider| provider.name == name)
+            .ok_or(ApiError::NotFound)?.name),
+        None => None,
+    };
+    info!("Trying rebind user {uuid} to {provider:?}");
+
+    if !state.bindings.rebind(uuid, provider).await.map_err(internal_and_log)? {
+        return Err(ApiError::NotFound);
+    }
     Ok("ok")
 }
 
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use anyhow::{anyhow, Context};
 use axum::{
@@ -6,12 +6,13 @@ use axum::{
 };
 use dashmap::DashMap;
 use thiserror::Error;
+use tokio::task::JoinSet;
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 
-use crate::{ApiError, ApiResult, AppState, TIMEOUT, USER_AGENT};
+use crate::{ApiError, ApiResult, AppState, USER_AGENT};
 
-use super::types::*;
+use super::{types::*, CircuitBreaker, ProvidersHealth};
 
 // It's an extractor that pulls a token from the Header.
 #[derive(PartialEq, Debug)]
@@ -49,43 +50,143 @@ where
 // End Extractor
 
 // Work with external APIs
-/// Get UUID from JSON response
-fn get_id_json(json: &serde_json::Value) -> Result<Uuid, uuid::Error> {
+/// Get value by path like `id` or `profile.id` (JSON pointer like `/profile/id` also works)
+fn get_json_path<'a>(json: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    if path.starts_with('/') {
+        json.pointer(path)
+    } else {
+        json.pointer(&format!("/{}", path.replace('.', "/")))
+    }
+}
+
+/// Get profile from JSON response
+fn get_profile_json(json: &serde_json::Value, provider: &AuthProvider) -> anyhow::Result<Profile> {
     trace!("json: {json:#?}"); // For debugging, we'll get to this later!
-    let uuid = Uuid::parse_str(json.get("id").unwrap().as_str().unwrap())?;
-    Ok(uuid)
+    let id = get_json_path(json, &provider.id_path).and_then(|id| id.as_str())
+        .ok_or_else(|| anyhow!("No UUID at `{}`", provider.id_path))?;
+    let name = get_json_path(json, &provider.name_path).and_then(|name| name.as_str())
+        .ok_or_else(|| anyhow!("No name at `{}`", provider.name_path))?;
+    let properties = match get_json_path(json, &provider.properties_path) {
+        Some(properties) => serde_json::from_value(properties.clone()).with_context(|| "Cant parse properties".to_string())?,
+        None => Vec::new(),
+    };
+    Ok(Profile {
+        id: Uuid::parse_str(id).with_context(|| "Cant parse UUID".to_string())?,
+        name: name.to_string(),
+        properties,
+    })
+}
+
+/// Get hasJoined endpoint of the provider, remembers it only if the API root answered
+async fn resolve_url(client: &reqwest::Client, provider: &AuthProvider, health: &ProvidersHealth) -> Result<String, FetchError> {
+    match provider.template {
+        ProviderTemplate::Custom => Ok(provider.url.clone()),
+        ProviderTemplate::AuthlibInjector => {
+            if let Some(url) = health.has_joined_url(&provider.url) {
+                return Ok(url);
+            }
+            // API Location Indication: the root may point to the real API root with a header
+            let res = client.get(&provider.url).send().await?;
+            let root = match res.headers().get("x-authlib-injector-api-location").and_then(|value| value.to_str().ok()) {
+                Some(location) => res.url().join(location).with_context(|| "Cant resolve API location".to_string())?.to_string(),
+                None => provider.url.clone(),
+            };
+            trace!("{} API root: {root}", provider.name);
+            let url = format!("{}/sessionserver/session/minecraft/hasJoined", root.trim_end_matches('/'));
+            if res.status().is_success() {
+                health.set_has_joined_url(provider.url.clone(), url.clone());
+            }
+            Ok(url)
+        },
+    }
 }
 
 #[derive(Debug, Error)]
-enum FetchError {
-    #[error("invalid response code (expected 200), found {0}.\n    Response: {1:#?}")]
+pub enum FetchError {
+    #[error("invalid response code (expected 200), found {0}")]
     WrongResponse(u16, Result<String, reqwest::Error>),
+    #[error("timed out")]
+    Timeout,
+    #[error("skipped, circuit breaker is open")]
+    CircuitOpen,
     #[error(transparent)]
-    SendError(#[from] reqwest::Error),
+    SendError(reqwest::Error),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 
 }
 
+impl FetchError {
+    /// Provider answered, but didn't confirm the player
+    pub fn is_miss(&self) -> bool {
+        matches!(self, FetchError::WrongResponse(code, _) if *code < 500)
+    }
+    /// Provider itself is unhealthy
+    pub fn is_failure(&self) -> bool {
+        match self {
+            FetchError::WrongResponse(code, _) => *code >= 500,
+            FetchError::CircuitOpen => false,
+            _ => true,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() { FetchError::Timeout } else { FetchError::SendError(err) }
+    }
+}
+
+/// Providers that failed to authenticate the user and why
+#[derive(Debug, Default)]
+pub struct AuthAttempts(pub Vec<(String, FetchError)>);
+
+impl AuthAttempts {
+    /// Returns true if none of the providers gave a definite answer
+    pub fn is_internal(&self) -> bool {
+        !self.0.iter().any(|(_, err)| err.is_miss())
+    }
+}
+
+impl std::fmt::Display for AuthAttempts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "no enabled authentication providers");
+        }
+        let attempts: Vec<String> = self.0.iter().map(|(name, err)| format!("{name}: {err}")).collect();
+        write!(f, "{}", attempts.join("; "))
+    }
+}
+
+type FetchResult = Result<(Profile, AuthProvider), FetchError>;
+
 async fn fetch_json(
     auth_provider: &AuthProvider,
+    health: &ProvidersHealth,
     server_id: &str,
     username: &str,
-) -> Result<(Uuid, AuthProvider), FetchError> {
-    let client = reqwest::Client::builder().timeout(TIMEOUT).user_agent(USER_AGENT).build().unwrap();
-    let url = auth_provider.url.clone();
-
-    let res = client
-        .get(url)
-        .query(&[("serverId", server_id), ("username", username)])
-        .send()
-        .await?;
+) -> FetchResult {
+    let client = reqwest::Client::builder().timeout(auth_provider.timeout()).user_agent(USER_AGENT).build().unwrap();
+    let url = resolve_url(&client, auth_provider, health).await?;
+    let params = [
+        (auth_provider.params.server_id.as_str(), server_id),
+        (auth_provider.params.username.as_str(), username),
+    ];
+
+    let mut req = match auth_provider.method {
+        ProviderMethod::Get => client.get(url).query(&params),
+        ProviderMethod::Post => client.post(url).json(&params.into_iter().collect::<HashMap<&str, &str>>()),
+    };
+    for (key, value) in &auth_provider.headers {
+        req = req.header(key, value);
+    }
+    let res = req.send().await?;
     trace!("{res:?}");
     match res.status().as_u16() {
         200 => {
             let json = serde_json::from_str::<serde_json::Value>(&res.text().await?).with_context(|| "Cant deserialize".to_string())?;
-            let uuid = get_id_json(&json).with_context(|| "Cant get UUID".to_string())?;
-            Ok((uuid, auth_provider.clone()))
+            let profile = get_profile_json(&json, auth_provider).with_context(|| "Cant get profile".to_string())?;
+            Ok((profile, auth_provider.clone()))
         }
         _ => Err(FetchError::WrongResponse(res.status().as_u16(), res.text().await)),
     }
@@ -93,63 +194,144 @@ async fn fetch_json(
 
 pub async fn has_joined(
     AuthProviders(authproviders): AuthProviders,
+    strategy: AuthStrategy,
+    health: Arc<ProvidersHealth>,
+    breaker: CircuitBreaker,
     server_id: &str,
     username: &str,
-) -> anyhow::Result<Option<(Uuid, AuthProvider)>> {
-    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
-
-    for provider in &authproviders {
-        tokio::spawn(fetch_and_send(
-            provider.clone(),
-            server_id.to_string(),
-            username.to_string(),
-            tx.clone()
-        ));
-    } 
-    let mut errors = Vec::new(); // Counting fetches what returns errors
-    let mut misses = Vec::new(); // Counting non OK results
-    let mut prov_count: usize = authproviders.len();
-    while prov_count > 0 {
-        if let Some(fetch_res) = rx.recv().await {
-            match fetch_res {
-                Ok(data) => return Ok(Some(data)),
-                Err(err) => {
-                    match err {
-                        FetchError::WrongResponse(code, data) => misses.push((code, data)),
-                        FetchError::SendError(err) => errors.push(err.to_string()),
-                        FetchError::Other(err) => errors.push(err.to_string()),
-                    }
-                },
-            }
-        } else {
-            error!("Unexpected behavior!");
-            return Err(anyhow!("Something went wrong..."))
-        }
-        prov_count -= 1;
+) -> Result<(Profile, AuthProvider), AuthAttempts> {
+    let mut providers: Vec<AuthProvider> = authproviders.into_iter().filter(|provider| provider.enabled).collect();
+    if strategy == AuthStrategy::Priority {
+        providers.sort_by_key(|provider| std::cmp::Reverse(provider.priority)); // Stable, so equal ones keep config order
     }
+    let ctx = Arc::new(FetchContext {
+        health,
+        breaker,
+        server_id: server_id.to_string(),
+        username: username.to_string(),
+    });
 
-    // Choosing what error return
+    let result = match strategy {
+        AuthStrategy::Race => race(providers, ctx).await,
+        AuthStrategy::Sequential => sequential(providers, ctx).await,
+        AuthStrategy::Priority => priority(providers, ctx).await,
+    };
 
-    // Returns if some internals errors occured
-    if !errors.is_empty() {
-        error!("Something wrong with your authentification providers!\nMisses: {misses:?}\nErrors: {errors:?}");
-        Err(anyhow::anyhow!("{:?}", errors))
-        
-    } else {
-        // Returning if user can't be authenticated
-        debug!("Misses: {misses:?}");
-        Ok(None)
+    // Choosing what error return
+    if let Err(attempts) = &result {
+        if attempts.is_internal() {
+            // Returns if none of the providers answered
+            error!("Something wrong with your authentification providers! {attempts}");
+        } else {
+            // Returning if user can't be authenticated
+            debug!("Misses: {attempts:?}");
+        }
     }
+    result
 }
 
-async fn fetch_and_send(
-    provider: AuthProvider,
+/// Everything needed to ask a provider
+struct FetchContext {
+    health: Arc<ProvidersHealth>,
+    breaker: CircuitBreaker,
     server_id: String,
     username: String,
-    tx: tokio::sync::mpsc::Sender<Result<(Uuid, AuthProvider), FetchError>>
-) {
-    let _ = tx.send(fetch_json(&provider, &server_id, &username).await)
-        .await.map_err( |err| trace!("fetch_and_send error [note: ok res returned and mpsc clossed]: {err:?}"));
+}
+
+impl FetchContext {
+    /// Asks provider if circuit breaker allows and records its health
+    async fn fetch(&self, provider: &AuthProvider) -> FetchResult {
+        if !self.health.allow(&provider.name, &self.breaker) {
+            return Err(FetchError::CircuitOpen);
+        }
+        let start = Instant::now();
+        let res = fetch_json(provider, &self.health, &self.server_id, &self.username).await;
+        self.health.record(&provider.name, res.as_ref().map(|_| ()), start.elapsed(), &self.breaker);
+        res
+    }
+}
+
+/// Asks all providers at once and returns the first success
+async fn race(
+    providers: Vec<AuthProvider>,
+    ctx: Arc<FetchContext>,
+) -> Result<(Profile, AuthProvider), AuthAttempts> {
+    let mut tasks = spawn_fetches(&providers, ctx);
+    let mut attempts = AuthAttempts::default();
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok((_, Ok(data))) => {
+                tasks.detach_all(); // Let the rest finish to keep their health up to date
+                return Ok(data)
+            },
+            Ok((index, Err(err))) => attempts.0.push((providers[index].name.clone(), err)),
+            Err(err) => error!("Fetch task failed! {err}"),
+        }
+    }
+    Err(attempts)
+}
+
+/// Asks providers one by one until the first success
+async fn sequential(
+    providers: Vec<AuthProvider>,
+    ctx: Arc<FetchContext>,
+) -> Result<(Profile, AuthProvider), AuthAttempts> {
+    let mut attempts = AuthAttempts::default();
+    for provider in providers {
+        match ctx.fetch(&provider).await {
+            Ok(data) => return Ok(data),
+            Err(err) => attempts.0.push((provider.name, err)),
+        }
+    }
+    Err(attempts)
+}
+
+/// Asks all providers at once, but waits for higher priority ones before accepting a success
+async fn priority(
+    providers: Vec<AuthProvider>,
+    ctx: Arc<FetchContext>,
+) -> Result<(Profile, AuthProvider), AuthAttempts> {
+    let mut tasks = spawn_fetches(&providers, ctx);
+    let mut results: Vec<Option<FetchResult>> = providers.iter().map(|_| None).collect();
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok((index, fetch_res)) => results[index] = Some(fetch_res),
+            Err(err) => error!("Fetch task failed! {err}"),
+        }
+        // The first provider (by priority) that is still pending or succeeded decides
+        match results.iter().find(|res| !matches!(res, Some(Err(_)))) {
+            Some(Some(Ok(data))) => {
+                tasks.detach_all();
+                return Ok(data.clone())
+            },
+            Some(_) => continue, // Still waiting for a higher priority provider
+            None => break, // Everyone failed
+        }
+    }
+    // Some task died, so taking any success that left
+    if let Some(Some(Ok(data))) = results.iter().find(|res| matches!(res, Some(Ok(_)))) {
+        return Ok(data.clone());
+    }
+    let attempts = providers.into_iter().zip(results)
+        .filter_map(|(provider, res)| match res {
+            Some(Err(err)) => Some((provider.name, err)),
+            _ => None,
+        })
+        .collect();
+    Err(AuthAttempts(attempts))
+}
+
+fn spawn_fetches(
+    providers: &[AuthProvider],
+    ctx: Arc<FetchContext>,
+) -> JoinSet<(usize, FetchResult)> {
+    let mut tasks = JoinSet::new();
+    for (index, provider) in providers.iter().enumerate() {
+        let provider = provider.clone();
+        let ctx = Arc::clone(&ctx);
+        tasks.spawn(async move { (index, ctx.fetch(&provider).await) });
+    }
+    tasks
 }
 
 // User manager
@@ -210,6 +392,7 @@ impl UManager {
                 if userinfo.rank != Userinfo::default().rank { exist.rank = userinfo.rank };
                 if userinfo.token.is_some() { exist.token = userinfo.token };
                 if userinfo.version != Userinfo::default().version { exist.version = userinfo.version };
+                if userinfo.skin.is_some() { exist.skin = userinfo.skin };
             }).or_insert(usercopy);
     }
     pub fn get(
@@ -225,16 +408,79 @@ impl UManager {
     ) -> Option<dashmap::mapref::one::Ref<'_, Uuid, Userinfo>> {
         self.registered.get(uuid)
     }
+    /// Adds bans of the `banned_user`, bans from other sources stay
     pub fn ban(&self, banned_user: &Userinfo) {
         self.registered.entry(banned_user.uuid)
             .and_modify(|exist| {
-                exist.banned = true;
-            }).or_insert(banned_user.clone());
+                exist.bans.extend(banned_user.bans.clone());
+                exist.banned = exist.is_banned();
+            }).or_insert_with(|| Userinfo { banned: banned_user.is_banned(), ..banned_user.clone() });
     }
-    pub fn unban(&self, uuid: &Uuid) {
+    /// Lifts the ban of the source, returns true if the user is still banned by another one
+    pub fn unban(&self, uuid: &Uuid, source: BanSource) -> bool {
         if let Some(mut user) = self.registered.get_mut(uuid) {
-            user.banned = false;
-        };
+            user.bans.remove(&source);
+            user.banned = user.is_banned();
+            user.banned
+        } else { false }
+    }
+    /// Users banned by the source
+    pub fn banned_by(&self, source: BanSource) -> Vec<Uuid> {
+        self.registered.iter().filter(|user| user.bans.contains_key(&source)).map(|user| user.uuid).collect()
+    }
+    /// Details of the active ban
+    pub fn ban_info(&self, uuid: &Uuid) -> Option<BanInfo> {
+        self.registered.get(uuid)?.active_ban().cloned()
+    }
+    /// Removes expired bans and returns users who aren't banned anymore
+    pub fn expire_bans(&self) -> Vec<Uuid> {
+        let mut expired = Vec::new();
+        for mut user in self.registered.iter_mut() {
+            if user.bans.values().any(|ban| ban.is_expired()) {
+                user.bans.retain(|_, ban| !ban.is_expired());
+                user.banned = user.is_banned();
+                if !user.banned {
+                    expired.push(user.uuid);
+                }
+            }
+        }
+        expired
+    }
+    pub fn set_shadow_ban(&self, uuid: &Uuid, shadow_banned: bool) -> Result<(), ()> {
+        if let Some(mut user) = self.registered.get_mut(uuid) {
+            user.shadow_banned = shadow_banned;
+            Ok(())
+        } else { Err(()) }
+    }
+    pub fn mute(&self, uuid: &Uuid, mute: BanInfo) -> Result<(), ()> {
+        if let Some(mut user) = self.registered.get_mut(uuid) {
+            user.mute = Some(mute);
+            Ok(())
+        } else { Err(()) }
+    }
+    pub fn unmute(&self, uuid: &Uuid) -> Result<(), ()> {
+        if let Some(mut user) = self.registered.get_mut(uuid) {
+            user.mute = None;
+            Ok(())
+        } else { Err(()) }
+    }
+    /// Details of the active mute
+    pub fn mute_info(&self, uuid: &Uuid) -> Option<BanInfo> {
+        self.registered.get(uuid)?.mute.clone().filter(|mute| !mute.is_expired())
+    }
+    /// Removes expired mutes and returns these users
+    pub fn expire_mutes(&self) -> Vec<Uuid> {
+        let mut expired = Vec::new();
+        for mut user in self.registered.iter_mut() {
+            if user.mute.as_ref().is_some_and(|mute| mute.is_expired()) {
+                user.mute = None;
+                expired.push(user.uuid);
+            }
+        }
+        expired
+    }
+    pub fn is_shadow_banned(&self, uuid: &Uuid) -> bool {
+        self.registered.get(uuid).is_some_and(|user| user.shadow_banned)
     }
     pub fn is_authenticated(&self, token: &String) -> bool {
         self.authenticated.contains_key(token)
@@ -243,14 +489,15 @@ impl UManager {
         self.registered.contains_key(uuid)
     }
     pub fn is_banned(&self, uuid: &Uuid) -> bool {
-        if let Some(user) = self.registered.get(uuid) { user.banned } else { false }
+        if let Some(user) = self.registered.get(uuid) { user.is_banned() } else { false }
     }
     pub fn count_authenticated(&self) -> usize {
         self.authenticated.len()
     }
     pub fn remove(&self, uuid: &Uuid) {
-        let token = self.registered.get(uuid).unwrap().token.clone().unwrap();
-        self.authenticated.remove(&token);
+        if let Some(token) = self.registered.get(uuid).and_then(|user| user.token.clone()) {
+            self.authenticated.remove(&token);
+        }
     }
 }
 // End of User manager
@@ -267,4 +514,215 @@ pub async fn check_auth(
         },
         None => Err(ApiError::BadRequest), 
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::CircuitState;
+    use base64::prelude::*;
+    use axum::{extract::Query, http::HeaderMap, response::IntoResponse, routing::{get, post}, Json, Router};
+    use serde_json::json;
+
+    const UUID: &str = "66004548-4de5-49de-bade-9c3933d8eb97";
+
+    /// Starts local provider and returns its address
+    async fn mock_server() -> String {
+        async fn has_joined(Query(query): Query<HashMap<String, String>>) -> axum::response::Response {
+            if query.get("serverId").map(String::as_str) == Some("joined") {
+                let textures = BASE64_STANDARD.encode(json!({ "textures": { "SKIN": { "url": "http://textures.minecraft.net/texture/steve" } } }).to_string());
+                Json(json!({
+                    "id": UUID.replace('-', ""),
+                    "name": query["username"],
+                    "properties": [{ "name": "textures", "value": textures, "signature": "" }]
+                })).into_response()
+            } else {
+                StatusCode::NO_CONTENT.into_response()
+            }
+        }
+        async fn custom(headers: HeaderMap, Json(body): Json<HashMap<String, String>>) -> axum::response::Response {
+            if headers.get("x-api-key").map(|key| key == "secret") != Some(true) {
+                return StatusCode::FORBIDDEN.into_response();
+            }
+            if body.get("sid").map(String::as_str) == Some("joined") {
+                Json(json!({ "profile": { "uuid": UUID, "nickname": body["user"] } })).into_response()
+            } else {
+                StatusCode::NO_CONTENT.into_response()
+            }
+        }
+        async fn broken() -> StatusCode {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        let app = Router::new()
+            .route("/session/hasJoined", get(has_joined))
+            .route("/custom", post(custom))
+            .route("/broken", get(broken))
+            .route("/", get(|| async { ([("X-Authlib-Injector-API-Location", "/api/yggdrasil/")], "Drasl") }))
+            .route("/api/yggdrasil/sessionserver/session/minecraft/hasJoined", get(has_joined));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    fn provider(name: &str, url: String) -> AuthProvider {
+        AuthProvider { name: name.to_string(), url, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_default_provider() {
+        let addr = mock_server().await;
+        let mojang = provider("Mojang", format!("{addr}/session/hasJoined"));
+        let health = ProvidersHealth::new();
+
+        let (profile, auth_provider) = fetch_json(&mojang, &health, "joined", "Steve").await.unwrap();
+        assert_eq!(profile.id, Uuid::parse_str(UUID).unwrap());
+        assert_eq!(profile.name, "Steve");
+        assert_eq!(profile.skin_url().as_deref(), Some("http://textures.minecraft.net/texture/steve"));
+        assert_eq!(auth_provider.name, "Mojang");
+        assert!(fetch_json(&mojang, &health, "not_joined", "Steve").await.unwrap_err().is_miss());
+    }
+
+    #[tokio::test]
+    async fn test_custom_provider() {
+        let addr = mock_server().await;
+        let mut custom = provider("Custom", format!("{addr}/custom"));
+        custom.method = ProviderMethod::Post;
+        custom.params = ProviderParams { server_id: "sid".to_string(), username: "user".to_string() };
+        custom.id_path = "profile.uuid".to_string();
+        custom.name_path = "/profile/nickname".to_string();
+        let health = ProvidersHealth::new();
+
+        assert!(matches!(fetch_json(&custom, &health, "joined", "Alex").await, Err(FetchError::WrongResponse(403, _))));
+        custom.headers.insert("X-Api-Key".to_string(), "secret".to_string());
+        let (profile, _) = fetch_json(&custom, &health, "joined", "Alex").await.unwrap();
+        assert_eq!(profile.name, "Alex");
+    }
+
+    #[tokio::test]
+    async fn test_authlib_injector_discovery() {
+        let addr = mock_server().await;
+        let mut drasl = provider("Drasl", format!("{addr}/"));
+        drasl.template = ProviderTemplate::AuthlibInjector;
+        let health = ProvidersHealth::new();
+
+        let (profile, _) = fetch_json(&drasl, &health, "joined", "Steve").await.unwrap();
+        assert_eq!(profile.id, Uuid::parse_str(UUID).unwrap());
+        let resolved = format!("{addr}/api/yggdrasil/sessionserver/session/minecraft/hasJoined");
+        assert_eq!(health.has_joined_url(&drasl.url), Some(resolved));
+
+        // Cached endpoint is used without asking the root again
+        health.set_has_joined_url(drasl.url.clone(), format!("{addr}/broken"));
+        assert!(fetch_json(&drasl, &health, "joined", "Steve").await.is_err());
+        health.clear_has_joined_urls();
+        assert!(fetch_json(&drasl, &health, "joined", "Steve").await.is_ok());
+
+        // Root that is down isn't remembered
+        let mut down = provider("Down", format!("{addr}/broken"));
+        down.template = ProviderTemplate::AuthlibInjector;
+        assert!(fetch_json(&down, &health, "joined", "Steve").await.is_err());
+        assert_eq!(health.has_joined_url(&down.url), None);
+    }
+
+    #[tokio::test]
+    async fn test_strategies() {
+        let addr = mock_server().await;
+        let mut broken = provider("Broken", format!("{addr}/broken"));
+        broken.priority = 1;
+        let mojang = provider("Mojang", format!("{addr}/session/hasJoined"));
+        let mut disabled = provider("Disabled", format!("{addr}/broken"));
+        disabled.enabled = false;
+        let providers = AuthProviders(vec![broken, mojang, disabled]);
+        // Without circuit breaker, so Broken is asked every time
+        let health = Arc::new(ProvidersHealth::new());
+
+        for strategy in [AuthStrategy::Race, AuthStrategy::Sequential, AuthStrategy::Priority] {
+            let (profile, auth_provider) = has_joined(providers.clone(), strategy, health.clone(), CircuitBreaker { failure_threshold: 0, cooldown: 0 }, "joined", "Steve").await.unwrap();
+            assert_eq!(profile.name, "Steve");
+            assert_eq!(auth_provider.name, "Mojang");
+
+            // One provider said no, so it's not an internal error even though the other one is broken
+            let attempts = has_joined(providers.clone(), strategy, health.clone(), CircuitBreaker { failure_threshold: 0, cooldown: 0 }, "not_joined", "Steve").await.unwrap_err();
+            assert_eq!(attempts.0.len(), 2);
+            assert!(!attempts.0.iter().find(|(name, _)| name == "Broken").unwrap().1.is_miss());
+            assert!(!attempts.is_internal());
+
+            // Nobody answered
+            let broken = AuthProviders(vec![provider("Broken", format!("{addr}/broken"))]);
+            let attempts = has_joined(broken, strategy, health.clone(), CircuitBreaker { failure_threshold: 0, cooldown: 0 }, "not_joined", "Steve").await.unwrap_err();
+            assert!(attempts.is_internal());
+        }
+
+        let attempts = has_joined(AuthProviders(vec![]), AuthStrategy::Race, health, CircuitBreaker::default(), "joined", "Steve").await.unwrap_err();
+        assert!(attempts.is_internal());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let addr = mock_server().await;
+        let providers = AuthProviders(vec![provider("Broken", format!("{addr}/broken"))]);
+        let health = Arc::new(ProvidersHealth::new());
+        let breaker = CircuitBreaker { failure_threshold: 2, cooldown: 60 };
+
+        for _ in 0..2 {
+            let attempts = has_joined(providers.clone(), AuthStrategy::Sequential, health.clone(), breaker, "joined", "Steve").await.unwrap_err();
+            assert!(matches!(attempts.0[0].1, FetchError::WrongResponse(500, _)));
+        }
+        let attempts = has_joined(providers.clone(), AuthStrategy::Sequential, health.clone(), breaker, "joined", "Steve").await.unwrap_err();
+        assert!(matches!(attempts.0[0].1, FetchError::CircuitOpen));
+
+        let status = health.status(&providers.0);
+        assert_eq!(status[0].circuit, CircuitState::Open);
+        assert_eq!(status[0].failures, 2);
+        assert_eq!(status[0].success_rate, 0.0);
+    }
+
+    #[test]
+    fn test_ban_sources() {
+        let umanager = UManager::new();
+        let uuid = Uuid::parse_str(UUID).unwrap();
+        let ban = |source, expires| Userinfo {
+            uuid,
+            banned: true,
+            bans: [(source, BanInfo { expires, ..Default::default() })].into(),
+            ..Default::default()
+        };
+
+        umanager.ban(&ban(BanSource::Api, None));
+        umanager.ban(&ban(BanSource::Minecraft, None));
+        assert!(umanager.unban(&uuid, BanSource::Minecraft), "API ban must stay");
+        assert!(umanager.is_banned(&uuid));
+        assert!(!umanager.unban(&uuid, BanSource::Api));
+        assert!(!umanager.is_banned(&uuid));
+
+        let expired = chrono::Utc::now() - chrono::TimeDelta::seconds(1);
+        umanager.ban(&ban(BanSource::Config, Some(expired)));
+        assert!(!umanager.is_banned(&uuid));
+        assert_eq!(umanager.expire_bans(), vec![uuid]);
+        assert!(umanager.get_by_uuid(&uuid).unwrap().bans.is_empty());
+    }
+
+    #[test]
+    fn test_mutes() {
+        let umanager = UManager::new();
+        let uuid = Uuid::parse_str(UUID).unwrap();
+        let mute = |seconds| BanInfo { expires: Some(chrono::Utc::now() + chrono::TimeDelta::seconds(seconds)), ..Default::default() };
+        assert!(umanager.mute(&uuid, mute(60)).is_err(), "unknown user");
+
+        umanager.insert_user(uuid, Userinfo { uuid, ..Default::default() });
+        umanager.mute(&uuid, mute(60)).unwrap();
+        assert!(umanager.get_by_uuid(&uuid).unwrap().is_muted());
+        assert!(umanager.expire_mutes().is_empty());
+
+        umanager.mute(&uuid, mute(-1)).unwrap();
+        assert!(!umanager.get_by_uuid(&uuid).unwrap().is_muted());
+        assert_eq!(umanager.mute_info(&uuid), None);
+        assert_eq!(umanager.expire_mutes(), vec![uuid]);
+        assert!(umanager.get_by_uuid(&uuid).unwrap().mute.is_none());
+        assert!(umanager.expire_mutes().is_empty(), "reported once");
+
+        umanager.mute(&uuid, BanInfo::default()).unwrap();
+        assert!(umanager.mute_info(&uuid).is_some(), "without expiry it's forever");
+        umanager.unmute(&uuid).unwrap();
+        assert!(!umanager.get_by_uuid(&uuid).unwrap().is_muted());
+    }
+}
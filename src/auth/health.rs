@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{AuthProvider, FetchError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitBreaker {
+    /// Consecutive failures before the provider is skipped, 0 disables circuit breaker
+    pub failure_threshold: u32,
+    /// How long the provider is skipped in seconds
+    pub cooldown: u64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CircuitState {
+    /// Provider is asked as usual
+    Closed,
+    /// Provider is skipped
+    Open,
+    /// Cooldown is over, next request will check the provider
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ProviderHealth {
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    last_latency: Duration,
+    /// Exponential moving average
+    avg_latency: Duration,
+    last_error: Option<String>,
+    open_until: Option<Instant>,
+}
+
+impl ProviderHealth {
+    fn circuit(&self) -> CircuitState {
+        match self.open_until {
+            Some(until) if until > Instant::now() => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+            None => CircuitState::Closed,
+        }
+    }
+}
+
+/// Health of the provider for the admin API and metrics
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderStatus {
+    pub name: String,
+    pub enabled: bool,
+    pub circuit: CircuitState,
+    pub successes: u64,
+    pub failures: u64,
+    pub success_rate: f64,
+    pub consecutive_failures: u32,
+    pub last_latency_ms: u128,
+    pub avg_latency_ms: u128,
+    pub last_error: Option<String>,
+}
+
+/// Health state of every auth provider by name
+#[derive(Debug, Default)]
+pub struct ProvidersHealth {
+    health: DashMap<String, ProviderHealth>,
+    /// Resolved hasJoined endpoints of authlib-injector providers by their URL
+    has_joined_urls: DashMap<String, String>,
+}
+
+impl ProvidersHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn has_joined_url(&self, url: &str) -> Option<String> {
+        self.has_joined_urls.get(url).map(|url| url.clone())
+    }
+    pub fn set_has_joined_url(&self, url: String, has_joined: String) {
+        self.has_joined_urls.insert(url, has_joined);
+    }
+    /// Called on config reload, so providers are resolved again
+    pub fn clear_has_joined_urls(&self) {
+        self.has_joined_urls.clear();
+    }
+    /// Checks that the provider can be asked, half-open circuit lets only one request through
+    pub fn allow(&self, provider: &str, breaker: &CircuitBreaker) -> bool {
+        let mut health = self.health.entry(provider.to_string()).or_default();
+        match health.circuit() {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => {
+                health.open_until = Some(Instant::now() + Duration::from_secs(breaker.cooldown));
+                true
+            },
+        }
+    }
+    pub fn record(&self, provider: &str, result: Result<(), &FetchError>, latency: Duration, breaker: &CircuitBreaker) {
+        let mut health = self.health.entry(provider.to_string()).or_default();
+        health.last_latency = latency;
+        health.avg_latency = if health.successes + health.failures == 0 {
+            latency
+        } else {
+            (health.avg_latency * 4 + latency) / 5
+        };
+        match result {
+            Err(err) if err.is_failure() => {
+                health.failures += 1;
+                health.consecutive_failures += 1;
+                health.last_error = Some(err.to_string());
+                if breaker.failure_threshold != 0 && health.consecutive_failures >= breaker.failure_threshold {
+                    if health.circuit() != CircuitState::Open {
+                        warn!("[Authentication] {provider} failed {} times in a row, skipping it for {} seconds", health.consecutive_failures, breaker.cooldown);
+                    }
+                    health.open_until = Some(Instant::now() + Duration::from_secs(breaker.cooldown));
+                }
+            },
+            _ => {
+                health.successes += 1;
+                health.consecutive_failures = 0;
+                if health.open_until.take().is_some() {
+                    info!("[Authentication] {provider} is back");
+                }
+            },
+        }
+    }
+    pub fn status(&self, providers: &[AuthProvider]) -> Vec<ProviderStatus> {
+        providers.iter().map(|provider| {
+            let health = self.health.get(&provider.name).map(|health| health.clone()).unwrap_or_default();
+            let total = health.successes + health.failures;
+            ProviderStatus {
+                name: provider.name.clone(),
+                enabled: provider.enabled,
+                circuit: health.circuit(),
+                successes: health.successes,
+                failures: health.failures,
+                success_rate: if total == 0 { 1.0 } else { health.successes as f64 / total as f64 },
+                consecutive_failures: health.consecutive_failures,
+                last_latency_ms: health.last_latency.as_millis(),
+                avg_latency_ms: health.avg_latency.as_millis(),
+                last_error: health.last_error,
+            }
+        }).collect()
+    }
+}
@@ -1,5 +1,8 @@
+#[allow(clippy::module_inception)]
 mod auth;
 mod types;
+mod health;
 
 pub use auth::*;
 pub use types::*;
+pub use health::*;
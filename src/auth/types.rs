@@ -1,4 +1,6 @@
-use chrono::Utc;
+use base64::prelude::*;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::{collections::BTreeMap, time::Duration};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -12,8 +14,19 @@ pub struct Userinfo {
     pub auth_provider: AuthProvider,
     pub token: Option<String>,
     pub version: String,
-    pub banned: bool
-    
+    /// Any source has banned the user
+    pub banned: bool,
+    /// Bans by the source, the user is banned while any of them is active
+    #[serde(default)]
+    pub bans: BTreeMap<BanSource, BanInfo>,
+    /// Pings aren't relayed and the avatar is visible only to the user
+    #[serde(default)]
+    pub shadow_banned: bool,
+    /// Pings aren't relayed while the mute is active
+    #[serde(default)]
+    pub mute: Option<BanInfo>,
+    /// Skin URL from the profile confirmed by the auth provider
+    pub skin: Option<String>,
 }
 
 impl Default for Userinfo {
@@ -26,7 +39,83 @@ impl Default for Userinfo {
             auth_provider: Default::default(),
             token: Default::default(),
             version: "0.1.4+1.20.1".to_string(),
-            banned: false
+            banned: false,
+            bans: BTreeMap::new(),
+            shadow_banned: false,
+            mute: None,
+            skin: None,
+        }
+    }
+}
+
+impl Userinfo {
+    pub fn is_banned(&self) -> bool {
+        self.bans.values().any(|ban| !ban.is_expired())
+    }
+    pub fn is_muted(&self) -> bool {
+        self.mute.as_ref().is_some_and(|mute| !mute.is_expired())
+    }
+    /// Active ban that lasts the longest
+    pub fn active_ban(&self) -> Option<&BanInfo> {
+        self.bans.values()
+            .filter(|ban| !ban.is_expired())
+            .max_by_key(|ban| ban.expires.map_or(DateTime::<Utc>::MAX_UTC, |expires| expires))
+    }
+}
+
+/// Where the ban came from, each source can lift only its own ban
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BanSource {
+    /// `advancedUsers` in the config
+    Config,
+    /// banned-players.json
+    Minecraft,
+    /// Admin API
+    Api,
+    /// Abuse detection
+    Automatic,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BanInfo {
+    pub reason: Option<String>,
+    /// Who banned the user
+    pub issuer: Option<String>,
+    pub created: DateTime<Utc>,
+    /// None means forever
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Default for BanInfo {
+    fn default() -> Self {
+        Self {
+            reason: None,
+            issuer: None,
+            created: Utc::now(),
+            expires: None,
+        }
+    }
+}
+
+impl BanInfo {
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= Utc::now())
+    }
+    /// Expiry `seconds` after `created`, None if it's too far to represent
+    pub fn expiry_after(created: DateTime<Utc>, seconds: u64) -> Option<DateTime<Utc>> {
+        i64::try_from(seconds).ok()
+            .and_then(TimeDelta::try_seconds)
+            .and_then(|duration| created.checked_add_signed(duration))
+    }
+    /// Reason and expiry shown to the user
+    pub fn details(&self) -> Option<String> {
+        let expires = self.expires.map(|expires| format!("Until {}", expires.format("%Y-%m-%d %H:%M UTC")));
+        match (&self.reason, expires) {
+            (Some(reason), Some(expires)) => Some(format!("{reason}. {expires}")),
+            (Some(reason), None) => Some(reason.clone()),
+            (None, expires) => expires,
         }
     }
 }
@@ -38,13 +127,107 @@ impl Default for Userinfo {
 pub struct AuthProvider {
     pub name: String,
     pub url: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Used by `AuthStrategy::Priority`, higher goes first
+    #[serde(default)]
+    pub priority: i32,
+    /// Request timeout in seconds
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(default)]
+    pub template: ProviderTemplate,
+    #[serde(default)]
+    pub method: ProviderMethod,
+    #[serde(default)]
+    pub params: ProviderParams,
+    /// Extra headers for every request, may contain secrets so they never leave the config
+    #[serde(default, skip_serializing)]
+    pub headers: BTreeMap<String, String>,
+    /// Path to the UUID in the response, like `id` or `profile.id`
+    #[serde(default = "default_id_path")]
+    pub id_path: String,
+    /// Path to the player name in the response
+    #[serde(default = "default_name_path")]
+    pub name_path: String,
+    /// Path to the profile properties (skin textures) in the response
+    #[serde(default = "default_properties_path")]
+    pub properties_path: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_timeout() -> u64 {
+    crate::TIMEOUT.as_secs()
+}
+
+fn default_id_path() -> String {
+    "id".to_string()
+}
+
+fn default_name_path() -> String {
+    "name".to_string()
+}
+
+fn default_properties_path() -> String {
+    "properties".to_string()
+}
+
+/// What the provider `url` points to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderTemplate {
+    /// Directly to the hasJoined endpoint
+    #[default]
+    Custom,
+    /// To the authlib-injector API root (Drasl, Blessing Skin, etc.), hasJoined endpoint is discovered from it
+    AuthlibInjector,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProviderMethod {
+    /// Parameters are sent in the query
+    #[default]
+    Get,
+    /// Parameters are sent in the JSON body
+    Post,
+}
+
+/// Names of the parameters sent to the provider
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderParams {
+    pub server_id: String,
+    pub username: String,
+}
+
+impl Default for ProviderParams {
+    fn default() -> Self {
+        Self {
+            server_id: "serverId".to_string(),
+            username: "username".to_string(),
+        }
+    }
 }
 
 impl Default for AuthProvider {
     fn default() -> Self {
         Self {
             name: "Unknown".to_string(),
-            url: Default::default()
+            url: Default::default(),
+            enabled: default_enabled(),
+            priority: Default::default(),
+            timeout: default_timeout(),
+            template: Default::default(),
+            method: Default::default(),
+            params: Default::default(),
+            headers: Default::default(),
+            id_path: default_id_path(),
+            name_path: default_name_path(),
+            properties_path: default_properties_path(),
         }
     }
 }
@@ -53,6 +236,34 @@ impl AuthProvider {
     pub fn is_empty(&self) -> bool {
         self.name == *"Unknown"
     }
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+}
+
+/// Player profile confirmed by the provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub id: Uuid,
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+impl Profile {
+    /// Skin URL from the base64 encoded `textures` property
+    pub fn skin_url(&self) -> Option<String> {
+        let textures = self.properties.iter().find(|property| property.name == "textures")?;
+        let textures = BASE64_STANDARD.decode(&textures.value).ok()?;
+        let textures: serde_json::Value = serde_json::from_slice(&textures).ok()?;
+        textures.pointer("/textures/SKIN/url")?.as_str().map(String::from)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -61,7 +272,20 @@ pub struct AuthProviders(pub Vec<AuthProvider>);
 
 pub fn default_authproviders() -> AuthProviders {
     AuthProviders(vec![
-        AuthProvider { name: "Mojang".to_string(), url: "https://sessionserver.mojang.com/session/minecraft/hasJoined".to_string() },
-        AuthProvider { name: "ElyBy".to_string(), url: "http://minecraft.ely.by/session/hasJoined".to_string() }
+        AuthProvider { name: "Mojang".to_string(), url: "https://sessionserver.mojang.com/session/minecraft/hasJoined".to_string(), ..Default::default() },
+        AuthProvider { name: "ElyBy".to_string(), url: "http://minecraft.ely.by/session/hasJoined".to_string(), ..Default::default() }
         ])
+}
+
+/// How `has_joined` queries providers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthStrategy {
+    /// All at once, the first success wins
+    #[default]
+    Race,
+    /// One by one in the configuration order
+    Sequential,
+    /// All at once, the success of the provider with the highest priority wins
+    Priority,
 }
\ No newline at end of file
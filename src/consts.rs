@@ -1,6 +1,8 @@
 pub const LOGGER_ENV: &str = "RUST_LOG";
 pub const CONFIG_ENV: &str = "RUST_CONFIG";
 pub const LOGS_ENV: &str = "LOGS_FOLDER";
+pub const BLOCKLIST_ENV: &str = "BLOCKLIST_FILE";
+pub const BINDINGS_ENV: &str = "BINDINGS_FILE";
 
 pub const SCULPTOR_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const REPOSITORY: &str = "shiroyashik/sculptor";
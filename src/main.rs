@@ -2,14 +2,12 @@ use anyhow::Result;
 use axum::{
     extract::DefaultBodyLimit, routing::{delete, get, post, put}, Router
 };
-use dashmap::DashMap;
 use tracing_panic::panic_hook;
 use tracing_subscriber::{fmt::{self, time::ChronoLocal}, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-use std::{path::PathBuf, sync::Arc};
-use tokio::{fs, sync::{broadcast, mpsc, RwLock}, time::Instant};
+use std::{future::IntoFuture, net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::{fs, sync::{Notify, RwLock}, time::Instant};
 use tower_http::trace::TraceLayer;
-use tracing::info;
-use uuid::Uuid;
+use tracing::{error, info, warn};
 
 // Consts
 mod consts;
@@ -21,21 +19,21 @@ pub use api::errors::{ApiResult, ApiError};
 // API
 mod api;
 use api::{
-    figura::{ws, info as api_info, profile as api_profile, auth as api_auth},
+    figura::{ws, ws_shutdown, info as api_info, profile as api_profile, auth as api_auth, AbuseTracker, ConnectionTracker, Hub},
     // v1::{},
 };
 
 // Auth
 mod auth;
-use auth::{UManager, check_auth};
+use auth::{UManager, ProvidersHealth, check_auth};
 
 // Config
 mod state;
-use state::Config;
+use state::{Blocklist, Config, Metrics, ProviderBindings};
 
 // Utils
 mod utils;
-use utils::{check_updates, get_log_file, update_advanced_users, update_bans_from_minecraft, FiguraVersions};
+use utils::{announcements, check_updates, expire_bans, get_log_file, update_advanced_users, update_bans_from_minecraft, FiguraVersions};
 
 #[derive(Debug, Clone)]
 pub struct AppState {
@@ -43,14 +41,24 @@ pub struct AppState {
     uptime: Instant,
     /// User manager
     user_manager: Arc<UManager>,
-    /// Send into WebSocket
-    session: Arc<DashMap<Uuid, mpsc::Sender<Vec<u8>>>>,
-    /// Ping broadcasts for WebSocket connections
-    broadcasts: Arc<DashMap<Uuid, broadcast::Sender<Vec<u8>>>>,
+    /// Fan-out of frames to WebSocket connections
+    hub: Arc<Hub>,
+    /// Open WebSocket connections
+    connections: Arc<ConnectionTracker>,
+    /// Strikes of abuse detection
+    abuse: Arc<AbuseTracker>,
+    /// Blocked avatar hashes
+    blocklist: Arc<Blocklist>,
+    /// Auth providers of UUIDs
+    bindings: Arc<ProviderBindings>,
     /// Current configuration
     config: Arc<RwLock<state::Config>>,
     /// Figura Versions
     figura_versions: Arc<RwLock<Option<FiguraVersions>>>,
+    /// Health of authentication providers
+    auth_health: Arc<ProvidersHealth>,
+    /// Server metrics
+    metrics: Arc<Metrics>,
 }
 
 #[tokio::main]
@@ -60,6 +68,8 @@ async fn main() -> Result<()> {
     let logger_env = std::env::var(LOGGER_ENV).unwrap_or_else(|_| "info".into());
     let config_file = std::env::var(CONFIG_ENV).unwrap_or_else(|_| "Config.toml".into());
     let logs_folder = std::env::var(LOGS_ENV).unwrap_or_else(|_| "logs".into());
+    let blocklist_file = std::env::var(BLOCKLIST_ENV).unwrap_or_else(|_| "avatars/blocklist.json".into());
+    let bindings_file = std::env::var(BINDINGS_ENV).unwrap_or_else(|_| "avatars/bindings.json".into());
 
     let file_appender = tracing_appender::rolling::never(&logs_folder, get_log_file(&logs_folder));
     let timer = ChronoLocal::new(String::from("%Y-%m-%dT%H:%M:%S%.3f%:z"));
@@ -109,33 +119,48 @@ async fn main() -> Result<()> {
     let state = AppState {
         uptime: Instant::now(),
         user_manager: Arc::new(UManager::new()),
-        session: Arc::new(DashMap::new()),
-        broadcasts: Arc::new(DashMap::new()),
+        hub: Arc::new(Hub::new()),
+        connections: Arc::new(ConnectionTracker::new()),
+        abuse: Arc::new(AbuseTracker::new()),
+        blocklist: Arc::new(Blocklist::load(blocklist_file.into())),
+        bindings: Arc::new(ProviderBindings::load(bindings_file.into())),
         figura_versions: Arc::new(RwLock::new(None)),
+        auth_health: Arc::new(ProvidersHealth::new()),
+        metrics: Arc::new(Metrics::new()),
         config,
     };
 
     // Automatic update of configuration while the server is running
     let config_update = Arc::clone(&state.config);
     let user_manager = Arc::clone(&state.user_manager);
+    let auth_health = Arc::clone(&state.auth_health);
     update_advanced_users(&config_update.read().await.advanced_users.clone(), &user_manager);
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-            let new_config = Config::parse(config_file.clone().into());
+            let new_config = match Config::try_parse(config_file.clone().into()) {
+                Ok(config) => config,
+                Err(err) => {
+                    error!("Configuration isn't reloaded: {err}");
+                    continue;
+                },
+            };
             let mut config = config_update.write().await;
 
             if new_config != *config {
                 info!("Server configuration modification detected!");
                 *config = new_config;
                 update_advanced_users(&config.advanced_users.clone(), &user_manager);
+                auth_health.clear_has_joined_urls();
             }
         }
     });
+    tokio::spawn(announcements(state.clone()));
+    tokio::spawn(expire_bans(state.clone()));
     if state.config.read().await.mc_folder.exists() {
         tokio::spawn(update_bans_from_minecraft(
             state.config.read().await.mc_folder.clone(),
-            Arc::clone(&state.user_manager)
+            state.clone()
         ));
     }
 
@@ -155,15 +180,38 @@ async fn main() -> Result<()> {
         .nest("/api", api)
         .route("/api/", get(check_auth))
         .route("/ws", get(ws))
-        .with_state(state)
+        .with_state(state.clone())
         .layer(TraceLayer::new_for_http().on_request(()))
         .route("/health", get(|| async { "ok" }));
 
     let listener = tokio::net::TcpListener::bind(listen).await?;
     info!("Listening on {}", listener.local_addr()?);
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    let stopping = Arc::new(Notify::new());
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown({
+            let (state, stopping) = (state.clone(), Arc::clone(&stopping));
+            async move {
+                shutdown_signal().await;
+                ws_shutdown(&state).await;
+                stopping.notify_one();
+            }
+        });
+    // Waiting for in-flight requests like avatar uploads, but not forever
+    let drain_timeout = async {
+        stopping.notified().await;
+        let timeout = state.config.read().await.shutdown.drain_timeout();
+        tokio::time::sleep(timeout).await;
+    };
+    tokio::select! {
+        result = server.into_future() => result?,
+        () = drain_timeout => warn!("Requests didn't finish in time, cutting them off"),
+    }
+    // WebSocket connections aren't tracked by axum after upgrade
+    let deadline = Instant::now() + state.config.read().await.shutdown.drain_timeout();
+    while state.connections.count() != 0 && Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    // Nothing to flush: users live in memory and avatars are written to disk on upload
     info!("Serve stopped. Closing...");
     Ok(())
 }
@@ -194,3 +242,26 @@ async fn shutdown_signal() {
         },
     }
 }
+
+#[cfg(test)]
+impl AppState {
+    /// State with the example config, the blocklist and bindings are saved to temporary files
+    pub fn test() -> Self {
+        let id = hex::encode(&utils::rand()[..8]);
+        let blocklist = std::env::temp_dir().join(format!("sculptor-blocklist-{id}.json"));
+        let bindings = std::env::temp_dir().join(format!("sculptor-bindings-{id}.json"));
+        Self {
+            uptime: Instant::now(),
+            user_manager: Arc::new(UManager::new()),
+            hub: Arc::new(Hub::new()),
+            connections: Arc::new(ConnectionTracker::new()),
+            abuse: Arc::new(AbuseTracker::new()),
+            blocklist: Arc::new(Blocklist::load(blocklist)),
+            bindings: Arc::new(ProviderBindings::load(bindings)),
+            figura_versions: Arc::new(RwLock::new(None)),
+            auth_health: Arc::new(ProvidersHealth::new()),
+            metrics: Arc::new(Metrics::new()),
+            config: Arc::new(RwLock::new(toml::from_str(include_str!("../Config.example.toml")).unwrap())),
+        }
+    }
+}
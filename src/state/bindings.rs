@@ -0,0 +1,81 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::RwLock};
+
+use tokio::{fs, sync::Mutex};
+use uuid::Uuid;
+
+/// Name of the auth provider that first confirmed the UUID, saved to the JSON file on every change
+#[derive(Debug)]
+pub struct ProviderBindings {
+    path: PathBuf,
+    providers: RwLock<BTreeMap<Uuid, String>>,
+    /// One write to the file at a time
+    saving: Mutex<()>,
+}
+
+impl ProviderBindings {
+    /// Missing file means nobody is bound yet
+    pub fn load(path: PathBuf) -> Self {
+        let providers = match std::fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).expect("Provider bindings are corrupted!"),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => panic!("Can't read provider bindings: {err}"),
+        };
+        Self { path, providers: RwLock::new(providers), saving: Mutex::new(()) }
+    }
+    /// Binds the UUID on the first login, returns false if it's bound to another provider
+    pub async fn bind(&self, uuid: Uuid, provider: &str) -> std::io::Result<bool> {
+        {
+            let mut providers = self.providers.write().unwrap();
+            match providers.get(&uuid) {
+                Some(bound) => return Ok(bound == provider),
+                None => providers.insert(uuid, provider.to_string()),
+            };
+        }
+        self.save().await.map(|_| true)
+    }
+    /// Binds the UUID to another provider, `None` unbinds it until the next login.
+    /// Returns false if the UUID wasn't bound
+    pub async fn rebind(&self, uuid: Uuid, provider: Option<String>) -> std::io::Result<bool> {
+        {
+            let mut providers = self.providers.write().unwrap();
+            if !providers.contains_key(&uuid) {
+                return Ok(false);
+            }
+            match provider {
+                Some(provider) => providers.insert(uuid, provider),
+                None => providers.remove(&uuid),
+            };
+        }
+        self.save().await.map(|_| true)
+    }
+    async fn save(&self) -> std::io::Result<()> {
+        let _saving = self.saving.lock().await;
+        let data = serde_json::to_string_pretty(&*self.providers.read().unwrap())?;
+        // Replaced at once, so a crash doesn't leave a half-written file
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, data).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn provider_binding() {
+    let path = std::env::temp_dir().join(format!("sculptor-bindings-{}.json", std::process::id()));
+    let uuid = Uuid::from_u128(0x66004548_4de5_49de_bade_9c3933d8eb97);
+
+    let bindings = ProviderBindings::load(path.clone());
+    assert!(bindings.bind(uuid, "Mojang").await.unwrap(), "bound on the first login");
+    assert!(bindings.bind(uuid, "Mojang").await.unwrap());
+    assert!(!bindings.bind(uuid, "ElyBy").await.unwrap());
+    assert!(!ProviderBindings::load(path.clone()).bind(uuid, "ElyBy").await.unwrap(), "binding survives restart");
+
+    assert!(bindings.rebind(uuid, Some("ElyBy".to_string())).await.unwrap());
+    assert!(!bindings.bind(uuid, "Mojang").await.unwrap());
+    assert!(ProviderBindings::load(path.clone()).bind(uuid, "ElyBy").await.unwrap());
+    assert!(bindings.rebind(uuid, None).await.unwrap());
+    assert!(bindings.bind(uuid, "Mojang").await.unwrap(), "unbound until the next login");
+
+    assert!(!bindings.rebind(Uuid::nil(), Some("Mojang".to_string())).await.unwrap(), "unknown UUID");
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,82 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::Mutex};
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockedAvatar {
+    pub reason: Option<String>,
+    pub issuer: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+/// Avatar hashes that can't be uploaded or shown, saved to the JSON file on every change
+#[derive(Debug)]
+pub struct Blocklist {
+    path: PathBuf,
+    hashes: RwLock<BTreeMap<String, BlockedAvatar>>,
+    /// One write to the file at a time
+    saving: Mutex<()>,
+}
+
+impl Blocklist {
+    /// Missing file is an empty blocklist
+    pub fn load(path: PathBuf) -> Self {
+        let hashes = match std::fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).expect("Blocklist is corrupted!"),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => panic!("Can't read the blocklist: {err}"),
+        };
+        Self { path, hashes: RwLock::new(hashes), saving: Mutex::new(()) }
+    }
+    pub fn is_blocked(&self, hash: &str) -> bool {
+        self.hashes.read().unwrap().contains_key(hash)
+    }
+    pub fn list(&self) -> BTreeMap<String, BlockedAvatar> {
+        self.hashes.read().unwrap().clone()
+    }
+    pub async fn block(&self, hash: String, avatar: BlockedAvatar) -> std::io::Result<()> {
+        self.hashes.write().unwrap().insert(hash, avatar);
+        self.save().await
+    }
+    /// Returns false if the hash wasn't blocked
+    pub async fn unblock(&self, hash: &str) -> std::io::Result<bool> {
+        if self.hashes.write().unwrap().remove(hash).is_none() {
+            return Ok(false);
+        }
+        self.save().await.map(|_| true)
+    }
+    async fn save(&self) -> std::io::Result<()> {
+        let _saving = self.saving.lock().await;
+        let data = serde_json::to_string_pretty(&*self.hashes.read().unwrap())?;
+        // Replaced at once, so a crash doesn't leave a half-written file
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, data).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+}
+
+/// Hash in the same form as in `equipped` of the profile
+pub fn normalize_hash(hash: &str) -> Option<String> {
+    let hash = hash.trim().to_ascii_lowercase();
+    (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())).then_some(hash)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn blocklist_persistence() {
+    let path = std::env::temp_dir().join(format!("sculptor-blocklist-{}.json", std::process::id()));
+    let hash = normalize_hash(&"AB".repeat(32)).unwrap();
+    assert_eq!(normalize_hash("abc"), None);
+
+    let blocklist = Blocklist::load(path.clone());
+    let avatar = BlockedAvatar { reason: Some("malware".to_string()), issuer: None, created: Utc::now() };
+    blocklist.block(hash.clone(), avatar.clone()).await.unwrap();
+    assert_eq!(Blocklist::load(path.clone()).list().get(&hash), Some(&avatar));
+    assert!(blocklist.unblock(&hash).await.unwrap());
+    assert!(!blocklist.unblock(&hash).await.unwrap());
+    assert!(!Blocklist::load(path.clone()).is_blocked(&hash));
+    std::fs::remove_file(path).unwrap();
+}
@@ -1,10 +1,12 @@
-use std::{collections::HashMap, io::Read, path::PathBuf};
+use std::{collections::HashMap, io::Read, path::PathBuf, time::Duration};
 
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
 use tracing::{debug, warn};
 use uuid::Uuid;
 
-use crate::auth::{default_authproviders, AuthProviders, Userinfo};
+use crate::auth::{default_authproviders, AuthProviders, AuthStrategy, BanInfo, BanSource, CircuitBreaker, Userinfo};
 
 #[derive(Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -14,8 +16,26 @@ pub struct Config {
     pub motd: CMotd,
     #[serde(default = "default_authproviders")]
     pub auth_providers: AuthProviders,
+    #[serde(default)]
+    pub auth_strategy: AuthStrategy,
+    #[serde(default)]
+    pub auth_circuit_breaker: CircuitBreaker,
     pub limitations: Limitations,
     #[serde(default)]
+    pub rate_limits: RateLimits,
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+    #[serde(default)]
+    pub abuse: AbuseConfig,
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    #[serde(default)]
+    pub announcements: Vec<Announcement>,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
     pub mc_folder: PathBuf,
     #[serde(default)]
     pub advanced_users: HashMap<Uuid, AdvancedUsers>,
@@ -41,6 +61,307 @@ pub struct Limitations {
     pub max_avatars: u64,
 }
 
+/// Advertised to clients by /api/limits, pings are also enforced on the server
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RateLimits {
+    /// Bytes of ping data per second
+    pub ping_size: u64,
+    /// Pings per second
+    pub ping_rate: u64,
+    pub equip: u64,
+    pub download: u64,
+    pub upload: u64,
+    /// What to do with pings over the limits
+    pub ping_policy: PingPolicy,
+    /// Violations before the connection is closed, 0 never closes
+    pub ping_close_after: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            ping_size: 1024,
+            ping_rate: 32,
+            equip: 1,
+            download: 50,
+            upload: 1,
+            ping_policy: PingPolicy::default(),
+            ping_close_after: 64,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PingPolicy {
+    /// Silently drop
+    Drop,
+    /// Drop and warn the sender with a toast
+    #[default]
+    Toast,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebSocketConfig {
+    /// Seconds to send the token before the connection is closed
+    pub auth_timeout: u64,
+    /// Connections from a single IP, 0 means no limit
+    pub max_per_ip: usize,
+    /// Connections that haven't sent the token yet, 0 means no limit
+    pub max_unauthenticated: usize,
+    /// Connections of a single user, 0 means no limit
+    pub max_per_user: usize,
+    /// Header with the client IP set by reverse proxy, like `X-Real-IP`.
+    /// With a list like `X-Forwarded-For` only the last entry is used, so the proxy must append to it
+    pub real_ip_header: Option<String>,
+    /// Seconds between keepalive pings, 0 disables keepalive
+    pub ping_interval: u64,
+    /// Seconds without any frame from the client before the connection is closed, 0 never closes.
+    /// Idle clients answer only keepalive pings, so it's ignored without them
+    pub idle_timeout: u64,
+    /// Frames queued for a slow client before the oldest ones are dropped
+    pub queue_size: usize,
+    /// Subscriptions per connection, 0 means no limit
+    pub max_subscriptions: usize,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            auth_timeout: 10,
+            max_per_ip: 0,
+            max_unauthenticated: 256,
+            max_per_user: 3,
+            real_ip_header: None,
+            ping_interval: 15,
+            idle_timeout: 60,
+            queue_size: 256,
+            max_subscriptions: 1024,
+        }
+    }
+}
+
+impl WebSocketConfig {
+    pub fn auth_timeout(&self) -> Duration {
+        Duration::from_secs(self.auth_timeout)
+    }
+    pub fn ping_interval(&self) -> Duration {
+        Duration::from_secs(self.ping_interval)
+    }
+    /// None if connections are never closed for idling
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.ping_interval != 0 && self.idle_timeout != 0).then(|| Duration::from_secs(self.idle_timeout))
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShutdownConfig {
+    /// Toast shown to connected players, empty disables it
+    pub toast: String,
+    pub description: Option<String>,
+    /// Seconds to wait for in-flight requests and closing connections
+    pub drain_timeout: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            toast: "Server restarting".to_string(),
+            description: None,
+            drain_timeout: 10,
+        }
+    }
+}
+
+impl ShutdownConfig {
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ModerationConfig {
+    /// Uploads wait for approval in `avatars/pending`, until then other players see the previous avatar
+    pub pre_approval: bool,
+}
+
+/// Rules checked on avatar upload, 0 means no limit
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ValidationConfig {
+    /// Total bytes of Lua scripts
+    pub max_script_size: u64,
+    /// Width or height of a texture in pixels
+    pub max_texture_size: u32,
+    /// Groups, cubes and meshes of all models
+    pub max_elements: u64,
+    /// Regular expressions that must not match any script
+    pub forbidden_patterns: Patterns,
+    /// External validator, runs after the rules passed
+    pub hook: Option<ValidationHook>,
+}
+
+impl ValidationConfig {
+    /// Any rule needs the avatar to be parsed
+    pub fn has_rules(&self) -> bool {
+        self.max_script_size != 0 || self.max_texture_size != 0 || self.max_elements != 0 || !self.forbidden_patterns.is_empty()
+    }
+}
+
+/// Regular expressions compiled when the config is loaded, an invalid one fails the loading
+#[derive(Clone, Debug, Default)]
+pub struct Patterns(Vec<Regex>);
+
+impl Patterns {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self, regex::Error> {
+        patterns.iter().map(|pattern| Regex::new(pattern.as_ref())).collect::<Result<_, _>>().map(Patterns)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Regex> {
+        self.0.iter()
+    }
+}
+
+impl<'de> Deserialize<'de> for Patterns {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::new(&Vec::<String>::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+impl PartialEq for Patterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().map(Regex::as_str).eq(other.iter().map(Regex::as_str))
+    }
+}
+
+/// Gets the avatar bytes and allows or denies it
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ValidationHook {
+    /// Program and arguments, the avatar goes to stdin, exit code 0 allows
+    pub command: Vec<String>,
+    /// The avatar is sent with POST, 2xx status allows
+    pub url: Option<String>,
+    /// Seconds to wait for the answer
+    pub timeout: u64,
+    /// Allow the upload if the hook fails or times out
+    pub fail_open: bool,
+}
+
+impl Default for ValidationHook {
+    fn default() -> Self {
+        Self {
+            command: Vec::new(),
+            url: None,
+            timeout: 10,
+            fail_open: false,
+        }
+    }
+}
+
+impl ValidationHook {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+}
+
+/// Automatic detection of ping spam. Each detection is a strike, strikes escalate:
+/// warning toast, temporary mute, disconnect, temporary ban
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AbuseConfig {
+    pub enabled: bool,
+    /// Seconds over which the heuristics are measured
+    pub window: u64,
+    /// Pings per second sustained over the window, including dropped ones, 0 disables it
+    pub max_ping_rate: u64,
+    /// Ping with more bytes of data is oversized, 0 disables it
+    pub oversized_ping: usize,
+    /// Oversized pings per window
+    pub max_oversized: u32,
+    /// Re-subscriptions (Unsub and then Sub of the same player) per second sustained over the window, 0 disables it
+    pub max_sub_churn: u64,
+    /// Seconds without detections before strikes are forgotten
+    pub forgive_after: u64,
+    /// Seconds of the automatic mute
+    pub mute_duration: u64,
+    /// Seconds of the automatic ban
+    pub ban_duration: u64,
+}
+
+impl Default for AbuseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: 10,
+            max_ping_rate: 64,
+            oversized_ping: 1024,
+            max_oversized: 5,
+            max_sub_churn: 32,
+            forgive_after: 600,
+            mute_duration: 300,
+            ban_duration: 3600,
+        }
+    }
+}
+
+impl AbuseConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window.max(1))
+    }
+    pub fn forgive_after(&self) -> Duration {
+        Duration::from_secs(self.forgive_after)
+    }
+}
+
+/// Message sent to players on schedule
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Announcement {
+    pub name: String,
+    /// Cron expression in local time, like "0 18 * * SAT"
+    pub cron: Option<String>,
+    /// Seconds between messages, used if there's no cron
+    pub interval: Option<u64>,
+    #[serde(flatten)]
+    pub message: AnnouncementMessage,
+    #[serde(default)]
+    pub to: AnnouncementTarget,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AnnouncementMessage {
+    Toast {
+        /// 0 - default, 1 - warning, 2 - error, 3 - cookie
+        #[serde(default)]
+        kind: u8,
+        title: String,
+        description: Option<String>,
+    },
+    Chat {
+        text: String,
+    },
+    Notice {
+        kind: u8,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AnnouncementTarget {
+    #[default]
+    All,
+    Rank(String),
+    Uuids(Vec<Uuid>),
+}
+
 #[derive(Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct AdvancedUsers {
@@ -59,14 +380,35 @@ pub struct AdvancedUsers {
 pub struct BannedPlayer {
     pub uuid: Uuid,
     pub name: String,
+    /// Dates are like "2024-05-01 12:00:00 +0000"
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub source: String,
+    /// Date or "forever"
+    #[serde(default)]
+    pub expires: String,
+    #[serde(default)]
+    pub reason: String,
+}
+
+fn parse_minecraft_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z").ok().map(|date| date.with_timezone(&Utc))
 }
 
 impl From<BannedPlayer> for Userinfo {
     fn from(val: BannedPlayer) -> Self {
+        let ban = BanInfo {
+            reason: Some(val.reason).filter(|reason| !reason.is_empty()),
+            issuer: Some(val.source).filter(|source| !source.is_empty()),
+            created: parse_minecraft_date(&val.created).unwrap_or_else(Utc::now),
+            expires: parse_minecraft_date(&val.expires), // "forever" too
+        };
         Userinfo {
             uuid: val.uuid,
             username: val.name,
             banned: true,
+            bans: [(BanSource::Minecraft, ban)].into(),
             ..Default::default()
         }
     }
@@ -74,11 +416,16 @@ impl From<BannedPlayer> for Userinfo {
 
 impl Config {
     pub fn parse(path: PathBuf) -> Self {
-        let mut file = std::fs::File::open(path).expect("Access denied or file doesn't exists!");
+        Self::try_parse(path).unwrap_or_else(|err| panic!("Can't load the config: {err}"))
+    }
+
+    /// Used on reload, so a broken edit doesn't stop the server
+    pub fn try_parse(path: PathBuf) -> anyhow::Result<Self> {
+        let mut file = std::fs::File::open(path)?;
         let mut data = String::new();
-        file.read_to_string(&mut data).unwrap();
+        file.read_to_string(&mut data)?;
 
-        toml::from_str(&data).unwrap()
+        Ok(toml::from_str(&data)?)
     }
 
     pub fn verify_token(&self, suspicious: &str) -> crate::ApiResult<()> {
@@ -100,3 +447,39 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn minecraft_ban() {
+    let bans: Vec<BannedPlayer> = serde_json::from_str(r#"[
+        {"uuid": "66004548-4de5-49de-bade-9c3933d8eb97", "name": "Shiroyashik", "created": "2024-05-01 12:00:00 +0300", "source": "Server", "expires": "2024-05-02 12:00:00 +0300", "reason": "Griefing"},
+        {"uuid": "66004548-4de5-49de-bade-9c3933d8eb98", "name": "Steve", "created": "2024-05-01 12:00:00 +0000", "source": "Server", "expires": "forever", "reason": "Banned by an operator."}
+    ]"#).unwrap();
+    let temporary: Userinfo = bans[0].clone().into();
+    let ban = &temporary.bans[&BanSource::Minecraft];
+    assert_eq!(ban.issuer.as_deref(), Some("Server"));
+    assert_eq!(ban.expires.unwrap().to_rfc3339(), "2024-05-02T09:00:00+00:00");
+    assert!(ban.is_expired());
+    assert_eq!(ban.details().unwrap(), "Griefing. Until 2024-05-02 09:00 UTC");
+    let permanent: Userinfo = bans[1].clone().into();
+    assert_eq!(permanent.bans[&BanSource::Minecraft].expires, None);
+}
+
+#[cfg(test)]
+#[test]
+fn forbidden_patterns() {
+    let config: ValidationConfig = toml::from_str(r#"forbiddenPatterns = ['loadstring\s*\(']"#).unwrap();
+    assert!(config.forbidden_patterns.iter().next().unwrap().is_match("loadstring (x)"));
+    assert_eq!(config.forbidden_patterns, Patterns::new(&[r"loadstring\s*\("]).unwrap());
+    let err = toml::from_str::<ValidationConfig>("forbiddenPatterns = ['[invalid']").unwrap_err();
+    assert!(err.to_string().contains("regex parse error"), "{err}");
+}
+
+#[cfg(test)]
+#[test]
+fn idle_timeout_needs_keepalive() {
+    let config = |ping_interval, idle_timeout| WebSocketConfig { ping_interval, idle_timeout, ..Default::default() };
+    assert_eq!(config(15, 60).idle_timeout(), Some(Duration::from_secs(60)));
+    assert_eq!(config(15, 0).idle_timeout(), None);
+    assert_eq!(config(0, 60).idle_timeout(), None, "quiet clients would be closed");
+}
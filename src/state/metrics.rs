@@ -0,0 +1,51 @@
+use std::fmt::Write;
+
+use dashmap::DashMap;
+
+/// Counters in Prometheus text format, labels are part of the name: `name{label="value"}`
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: DashMap<String, u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn inc(&self, name: &str) {
+        self.add(name, 1);
+    }
+    pub fn add(&self, name: &str, value: u64) {
+        *self.counters.entry(name.to_string()).or_default() += value;
+    }
+    /// Renders counters together with gauges calculated at the moment of the request
+    pub fn render(&self, gauges: &[(String, f64)]) -> String {
+        let mut counters: Vec<(String, u64)> = self.counters.iter().map(|c| (c.key().clone(), *c.value())).collect();
+        counters.sort();
+
+        let mut out = String::new();
+        for (name, value) in counters {
+            let _ = writeln!(out, "{name} {value}");
+        }
+        for (name, value) in gauges {
+            let _ = writeln!(out, "{name} {value}");
+        }
+        out
+    }
+}
+
+/// Label set like `{provider="Ely.by"}`, values are escaped as the text format requires
+pub fn labels(labels: &[(&str, &str)]) -> String {
+    let labels: Vec<String> = labels.iter().map(|(name, value)| {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+        format!("{name}=\"{value}\"")
+    }).collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+#[test]
+fn label_escaping() {
+    assert_eq!(labels(&[("result", "success"), ("provider", "Ely.by")]), r#"{result="success",provider="Ely.by"}"#);
+    assert_eq!(labels(&[("provider", "a\\b\"c\nd")]), r#"{provider="a\\b\"c\nd"}"#);
+}
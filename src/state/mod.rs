@@ -1,4 +1,11 @@
+mod bindings;
+mod blocklist;
 mod config;
+mod metrics;
+#[allow(clippy::module_inception)]
 mod state;
 
-pub use config::*;
\ No newline at end of file
+pub use bindings::*;
+pub use blocklist::*;
+pub use config::*;
+pub use metrics::*;
\ No newline at end of file
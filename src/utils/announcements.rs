@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use croner::Cron;
+use tracing::{debug, error, info};
+
+use crate::{
+    api::figura::S2CMessage,
+    state::{Announcement, AnnouncementMessage, AnnouncementTarget},
+    AppState,
+};
+
+struct Scheduled {
+    announcement: Announcement,
+    cron: Option<Cron>,
+    next: Option<DateTime<Local>>,
+}
+
+impl Scheduled {
+    fn new(announcement: Announcement) -> Option<Self> {
+        let cron = match (&announcement.cron, announcement.interval) {
+            (Some(cron), _) => match Cron::new(cron).with_seconds_optional().parse() {
+                Ok(cron) => Some(cron),
+                Err(e) => {
+                    error!("[Announcements] Invalid cron for {}: {e}", announcement.name);
+                    return None;
+                },
+            },
+            (None, Some(interval)) if interval != 0 => None,
+            _ => {
+                error!("[Announcements] {} has no schedule! Set cron or interval", announcement.name);
+                return None;
+            },
+        };
+        let mut scheduled = Self { announcement, cron, next: None };
+        scheduled.next = scheduled.after(Local::now());
+        Some(scheduled)
+    }
+    fn after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        match (&self.cron, self.announcement.interval) {
+            (Some(cron), _) => cron.find_next_occurrence(&time, false).ok(),
+            (None, Some(interval)) => Some(time + Duration::from_secs(interval)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Sends scheduled messages from the config, reloads schedules with the config
+pub async fn announcements(state: AppState) {
+    let mut current: Vec<Announcement> = Vec::new();
+    let mut scheduled: Vec<Scheduled> = Vec::new();
+    loop {
+        {
+            let config = state.config.read().await;
+            if config.announcements != current {
+                current = config.announcements.clone();
+                scheduled = current.iter().cloned().filter_map(Scheduled::new).collect();
+                info!("[Announcements] Loaded {} schedules", scheduled.len());
+            }
+        }
+        let now = Local::now();
+        for item in scheduled.iter_mut() {
+            if item.next.is_some_and(|next| next <= now) {
+                let count = send(&state, &item.announcement);
+                info!("[Announcements] {} sent to {count} connections", item.announcement.name);
+                item.next = item.after(now);
+                debug!("[Announcements] Next {} at {:?}", item.announcement.name, item.next);
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+fn send(state: &AppState, announcement: &Announcement) -> usize {
+    let msg = match &announcement.message {
+        AnnouncementMessage::Toast { kind, title, description } => S2CMessage::Toast(*kind, title, description.as_deref()),
+        AnnouncementMessage::Chat { text } => S2CMessage::Chat(text),
+        AnnouncementMessage::Notice { kind } => S2CMessage::Notice(*kind),
+    }.to_vec();
+    match &announcement.to {
+        AnnouncementTarget::All => state.hub.send_all(msg),
+        AnnouncementTarget::Rank(rank) => state.hub.online().iter()
+            .filter(|uuid| state.user_manager.get_by_uuid(uuid).is_some_and(|user| user.rank.eq_ignore_ascii_case(rank)))
+            .map(|uuid| state.hub.send(uuid, msg.clone()))
+            .sum(),
+        AnnouncementTarget::Uuids(uuids) => uuids.iter().map(|uuid| state.hub.send(uuid, msg.clone())).sum(),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn announcement_schedule() {
+    use chrono::TimeZone;
+
+    let announcement = |cron: Option<&str>, interval| Announcement {
+        name: "test".to_string(),
+        cron: cron.map(str::to_string),
+        interval,
+        message: AnnouncementMessage::Chat { text: "Hi!".to_string() },
+        to: AnnouncementTarget::All,
+    };
+    let at = |day, hour, min, sec| Local.with_ymd_and_hms(2026, 1, day, hour, min, sec).unwrap();
+    let thursday = at(1, 12, 0, 0);
+
+    let weekly = Scheduled::new(announcement(Some("0 18 * * SAT"), None)).unwrap();
+    assert!(weekly.next.is_some());
+    assert_eq!(weekly.after(thursday), Some(at(3, 18, 0, 0)));
+    assert_eq!(weekly.after(at(3, 18, 0, 0)), Some(at(10, 18, 0, 0)), "fired one isn't next");
+    let seconds = Scheduled::new(announcement(Some("*/30 * * * * *"), Some(5))).unwrap();
+    assert_eq!(seconds.after(thursday), Some(at(1, 12, 0, 30)), "cron goes before interval");
+
+    let interval = Scheduled::new(announcement(None, Some(90))).unwrap();
+    assert_eq!(interval.after(thursday), Some(at(1, 12, 1, 30)));
+
+    assert!(Scheduled::new(announcement(Some("not a cron"), Some(90))).is_none());
+    assert!(Scheduled::new(announcement(Some("61 * * * *"), None)).is_none());
+    assert!(Scheduled::new(announcement(None, Some(0))).is_none());
+    assert!(Scheduled::new(announcement(None, None)).is_none());
+}
@@ -1,7 +1,12 @@
+#[allow(clippy::module_inception)]
 mod utils;
 mod check_updates;
 mod motd;
+mod token_bucket;
+mod announcements;
 
 pub use utils::*;
 pub use motd::*;
-pub use check_updates::*;
\ No newline at end of file
+pub use check_updates::*;
+pub use token_bucket::*;
+pub use announcements::*;
\ No newline at end of file
@@ -0,0 +1,59 @@
+use std::time::Instant;
+
+/// Classic token bucket: holds up to `capacity` tokens and refills `rate` tokens per second
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full
+    pub fn new(capacity: u64, rate: u64) -> Self {
+        Self {
+            capacity: capacity as f64,
+            rate: rate as f64,
+            tokens: capacity as f64,
+            last: Instant::now(),
+        }
+    }
+    /// Takes `amount` tokens if there are enough of them
+    pub fn try_take(&mut self, amount: u64) -> bool {
+        self.try_take_at(amount, Instant::now())
+    }
+    /// Checks without taking, to take from several buckets only if all of them have enough
+    pub fn can_take(&mut self, amount: u64) -> bool {
+        self.refill(Instant::now());
+        self.tokens >= amount as f64
+    }
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+    }
+    fn try_take_at(&mut self, amount: u64, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= amount as f64 {
+            self.tokens -= amount as f64;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn token_bucket_refill() {
+    use std::time::Duration;
+    let start = Instant::now();
+    let mut bucket = TokenBucket { last: start, ..TokenBucket::new(4, 2) };
+    assert!(bucket.try_take_at(3, start));
+    assert!(!bucket.try_take_at(2, start));
+    assert!(bucket.try_take_at(2, start + Duration::from_millis(500)));
+    // Never overfills
+    assert!(!bucket.try_take_at(5, start + Duration::from_secs(60)));
+    assert!(bucket.try_take_at(4, start + Duration::from_secs(60)));
+}
@@ -8,7 +8,7 @@ use tracing::{error, info};
 use uuid::Uuid;
 use chrono::prelude::*;
 
-use crate::{auth::{UManager, Userinfo}, state::{AdvancedUsers, BannedPlayer}};
+use crate::{api::figura::{enforce_ban, notify_mute, profile::send_event}, auth::{BanInfo, BanSource, UManager, Userinfo}, state::{AdvancedUsers, BannedPlayer}, AppState};
 
 // Core functions
 pub fn rand() -> [u8; 50] {
@@ -40,6 +40,9 @@ pub fn update_advanced_users(value: &std::collections::HashMap<Uuid, AdvancedUse
                 uuid: *uuid,
                 username: userdata.username.clone(),
                 banned: userdata.banned,
+                bans: if userdata.banned {
+                    [(BanSource::Config, BanInfo { issuer: Some("Config".to_string()), ..Default::default() })].into()
+                } else { Default::default() },
                 ..Default::default()
             }
         )})
@@ -51,9 +54,16 @@ pub fn update_advanced_users(value: &std::collections::HashMap<Uuid, AdvancedUse
             umanager.ban(&userinfo)
         }
     }
+    // Removed from the config
+    for uuid in umanager.banned_by(BanSource::Config) {
+        if !value.get(&uuid).is_some_and(|user| user.banned) {
+            umanager.unban(&uuid, BanSource::Config);
+        }
+    }
 }
 
-pub async fn update_bans_from_minecraft(folder: PathBuf, umanager: std::sync::Arc<UManager>) {
+pub async fn update_bans_from_minecraft(folder: PathBuf, state: AppState) {
+    let umanager = &state.user_manager;
     let path = folder.join("banned-players.json");
     let mut file = tokio::fs::File::open(path.clone()).await.expect("Access denied or banned-players.json doesn't exists!");
     let mut data = String::new();
@@ -85,11 +95,15 @@ pub async fn update_bans_from_minecraft(folder: PathBuf, umanager: std::sync::Ar
 
         if new_bans != old_bans {
             info!("Minecraft ban list modification detected!");
-            let unban: Vec<&BannedPlayer> = old_bans.iter().filter(|user| !new_bans.contains(user)).collect();
+            let unban: Vec<&BannedPlayer> = old_bans.iter().filter(|user| !new_bans.iter().any(|new| new.uuid == user.uuid)).collect();
             let mut unban_names = unban.iter().map(|user| user.name.clone()).collect::<Vec<String>>().join(", ");
             if !unban.is_empty() {
                 for player in unban {
-                    umanager.unban(&player.uuid);
+                    if umanager.unban(&player.uuid, BanSource::Minecraft) {
+                        info!("{} is still banned by another source", player.name);
+                    } else {
+                        send_event(&state, &player.uuid).await;
+                    }
                 }
             } else { unban_names = String::from("-")};
             let ban: Vec<&BannedPlayer> = new_bans.iter().filter(|user| !old_bans.contains(user)).collect();
@@ -97,6 +111,7 @@ pub async fn update_bans_from_minecraft(folder: PathBuf, umanager: std::sync::Ar
             if !ban.is_empty() {
                 for player in ban {
                     umanager.ban(&player.clone().into());
+                    enforce_ban(&state, &player.uuid).await;
                 }
             } else { ban_names = String::from("-")};
             info!("List of changes:\n    Banned: {ban_names}\n    Unbanned: {unban_names}");
@@ -106,6 +121,20 @@ pub async fn update_bans_from_minecraft(folder: PathBuf, umanager: std::sync::Ar
     }
 }
 
+/// Lifts temporary bans and mutes when they expire
+pub async fn expire_bans(state: AppState) {
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+        for uuid in state.user_manager.expire_bans() {
+            info!("Ban of {uuid} expired");
+            send_event(&state, &uuid).await; // Avatar is visible again
+        }
+        for uuid in state.user_manager.expire_mutes() {
+            info!("Mute of {uuid} expired");
+            notify_mute(&state, &uuid);
+        }
+    }
+}
 
 pub fn format_uuid(uuid: &Uuid) -> String {
     // let uuid = Uuid::parse_str(&uuid)?; TODO: Вероятно format_uuid стоит убрать
@@ -119,17 +148,20 @@ pub fn calculate_file_sha256(file_path: &str) -> Result<String, std::io::Error>
     let mut content = Vec::new();
     file.read_to_end(&mut content)?;
 
+    Ok(calculate_sha256(&content))
+}
+
+/// Avatar hash as Figura calculates it
+pub fn calculate_sha256(content: &[u8]) -> String {
     // Convert the content to base64
-    let base64_content = BASE64_STANDARD.encode(&content);
+    let base64_content = BASE64_STANDARD.encode(content);
 
     // Calculate the SHA-256 hash of the base64 string
     let binding = digest(&digest::SHA256, base64_content.as_bytes());
     let hash = binding.as_ref();
 
     // Convert the hash to a hexadecimal string
-    let hex_hash = hex::encode(hash);
-
-    Ok(hex_hash)
+    hex::encode(hash)
 }
 
 pub fn get_log_file(folder: &str) -> String {